@@ -1,6 +1,7 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short, token, Address, Env, Map, String, Vec,
+    contract, contractimpl, contracttype, symbol_short, token, xdr::ToXdr, Address, Bytes, BytesN, Env,
+    Map, String, Vec,
 };
 use soroban_token_sdk::TokenUtils;
 
@@ -46,6 +47,241 @@ pub enum ActivityType {
     Achievement,    // Unlocking achievements
 }
 
+/// Consecutive-day streak for a user on one activity type
+#[contracttype]
+#[derive(Clone)]
+pub struct StreakInfo {
+    pub current_streak: u32,
+    pub last_day: u64,
+}
+
+/// Streak multiplier configuration (basis points on top of the base reward)
+#[contracttype]
+#[derive(Clone)]
+pub struct StreakConfig {
+    pub bonus_bps_per_day: u32,
+    pub max_bonus_bps: u32,
+}
+
+/// Referral relationship, registered once per referee
+#[contracttype]
+#[derive(Clone)]
+pub struct Referral {
+    pub referrer: Address,
+    pub registered_at: u64,
+}
+
+/// Referral payout configuration
+#[contracttype]
+#[derive(Clone)]
+pub struct ReferralConfig {
+    pub reward_bps: u32,     // Share of referee's activity rewards paid to referrer
+    pub duration_days: u64,  // How long after registration the referrer earns
+}
+
+/// Outcome of a single activity reward attempt
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum RewardStatus {
+    Rewarded,
+    NotConfigured,
+    Disabled,
+    OnCooldown,
+    DailyCapReached,
+    MaxSupplyReached,
+    DailyMintLimitReached,
+    EmissionBudgetReached,
+    MinterQuotaReached,
+    ProofRequired,
+}
+
+/// Per-entry result of `reward_activities_batch`
+#[contracttype]
+#[derive(Clone)]
+pub struct BatchRewardResult {
+    pub user: Address,
+    pub activity_type_id: u32,
+    pub status: RewardStatus,
+    pub amount: i128,
+}
+
+/// Reversible hold on a user's credits (e.g. a cancellable tournament entry)
+#[contracttype]
+#[derive(Clone)]
+pub struct CreditHold {
+    pub id: BytesN<32>,
+    pub user: Address,
+    pub operator: Address,
+    pub amount: i128,
+    pub expiry: u64,
+    pub status: HoldStatus,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum HoldStatus {
+    Active,
+    Captured,
+    Released,
+}
+
+/// Who may move credits between addresses
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransferPolicy {
+    Soulbound,    // No transfers at all
+    Allowlisted,  // Only to/from allowlisted contracts (e.g. tournament-raffle)
+    Open,         // Unrestricted
+}
+
+/// SEP-41 allowance with ledger expiration
+#[contracttype]
+#[derive(Clone)]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
+
+/// Where credits go when spent in a category
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpendSink {
+    Burn,               // Reduce total supply
+    Treasury,           // Route to the configured treasury
+    Recipient(Address), // Route to a fixed address (e.g. a game creator)
+    Host,               // Route to the beneficiary supplied per spend (e.g. tournament host)
+}
+
+/// Per-category spend accounting
+#[contracttype]
+#[derive(Clone)]
+pub struct SpendCategoryStats {
+    pub total_spent: i128,
+    pub total_burned: i128,
+    pub total_routed: i128,
+    pub spend_count: u64,
+}
+
+/// Privileged roles
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Role {
+    Minter,
+    Burner,
+    HoldOperator,
+}
+
+/// Role membership, optionally time-limited
+#[contracttype]
+#[derive(Clone)]
+pub struct RoleGrant {
+    pub account: Address,
+    pub expires_at: u64,  // 0 = never expires
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoleAction {
+    Granted,
+    Revoked,
+}
+
+/// Audit log entry for a role change
+#[contracttype]
+#[derive(Clone)]
+pub struct RoleChange {
+    pub id: u64,
+    pub role: Role,
+    pub account: Address,
+    pub action: RoleAction,
+    pub expires_at: u64,
+    pub timestamp: u64,
+}
+
+/// Proof-of-play requirement for an activity type
+#[contracttype]
+#[derive(Clone)]
+pub struct ProofOfPlayConfig {
+    pub required: bool,
+    pub min_duration_seconds: u64,
+}
+
+/// Hash-committed play session awaiting reveal
+#[contracttype]
+#[derive(Clone)]
+pub struct PlaySession {
+    pub activity_type_id: u32,
+    pub committed_at: u64,
+}
+
+/// Recurring credit subscription plan (e.g. monthly "Arcade Pro")
+#[contracttype]
+#[derive(Clone)]
+pub struct SubscriptionPlan {
+    pub id: u32,
+    pub name: String,
+    pub price: i128,
+    pub period_seconds: u64,
+    pub spend_category: u32,  // Spend sink category the payments are routed through
+    pub is_active: bool,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubscriptionStatus {
+    Active,
+    Cancelled,  // No further charges; access lasts until paid_until
+    Expired,    // A due payment could not be collected
+}
+
+/// A user's subscription to a plan
+#[contracttype]
+#[derive(Clone)]
+pub struct Subscription {
+    pub plan_id: u32,
+    pub started_at: u64,
+    pub paid_until: u64,
+    pub status: SubscriptionStatus,
+}
+
+/// Batch of redeemable credit vouchers (gift cards)
+#[contracttype]
+#[derive(Clone)]
+pub struct VoucherBatch {
+    pub id: u32,
+    pub expiry: u64,
+    pub max_redemptions: u32,
+    pub voucher_count: u32,
+    pub redeemed_count: u32,
+    pub redeemed_credits: i128,
+    pub is_revoked: bool,
+}
+
+/// A single voucher, keyed by sha256(code)
+#[contracttype]
+#[derive(Clone)]
+pub struct Voucher {
+    pub batch_id: u32,
+    pub amount: i128,
+    pub is_redeemed: bool,
+    pub is_revoked: bool,
+}
+
+/// Scheduled promotion on a credit package
+#[contracttype]
+#[derive(Clone)]
+pub struct Promotion {
+    pub id: u32,
+    pub package_id: u32,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub bonus_bps: u32,       // Extra credits on top of the package total
+    pub per_user_limit: u32,  // 0 = unlimited
+    pub global_cap: u32,      // 0 = unlimited
+    pub sold_count: u32,
+    pub is_active: bool,
+}
+
 /// Credit transaction record
 #[contracttype]
 #[derive(Clone)]
@@ -70,13 +306,48 @@ pub enum CreditTxType {
     GameReward,     // Earned from playing games
     RadioReward,    // Earned from listening to radio
     ChatReward,     // Earned from chatting
+    ReferralReward, // Earned from a referee's activity
+    Voucher,        // Redeemed gift card / voucher
+}
+
+/// Storage keys for package promotions
+#[contracttype]
+pub enum PromotionKey {
+    Promotion(u32),                 // promotion_id -> Promotion
+    Counter,
+    PackagePromotions(u32),         // package_id -> promotion ids
+    UserPurchases(u32, Address),    // (promotion_id, user) -> purchases
+}
+
+/// Storage keys for vouchers
+#[contracttype]
+pub enum VoucherKey {
+    Batch(u32),                 // batch_id -> VoucherBatch
+    BatchCounter,
+    Voucher(BytesN<32>),        // sha256(code) -> Voucher
+}
+
+/// Storage keys for subscriptions
+#[contracttype]
+pub enum SubscriptionKey {
+    Plan(u32),                      // plan_id -> SubscriptionPlan
+    PlanCounter,
+    Subscription(Address, u32),     // (user, plan_id) -> Subscription
+}
+
+/// Storage keys for proof-of-play sessions
+#[contracttype]
+pub enum ProofKey {
+    Config(u32),                    // activity_type_id -> ProofOfPlayConfig
+    Session(Address, BytesN<32>),   // (user, commitment) -> PlaySession
 }
 
 #[contracttype]
 pub enum DataKey {
     Admin,
-    MinterRole,
-    BurnerRole,
+    RoleMembers(Role),          // role -> Vec<RoleGrant>
+    RoleChangeCounter,
+    RoleChange(u64),            // change_id -> RoleChange
     USDCToken,
     TotalSupply,
     MaxSupply,
@@ -93,6 +364,34 @@ pub enum DataKey {
     // Activity reward configs
     ActivityRewardConfig(u32),  // activity_type_id -> ActivityReward
     DailyActivityCap(u32),     // activity_type_id -> max rewards per day
+    // Off-chain (card) purchases
+    PaymentOracle,              // ed25519 public key signing purchase receipts
+    RedeemedReceipt(BytesN<32>), // receipt_id -> redeemed flag
+    // Streaks and referrals
+    StreakConfig,
+    UserStreak(Address, u32),   // (user, activity_type_id) -> StreakInfo
+    ReferralConfig,
+    Referral(Address),          // referee -> Referral
+    ReferralEarnings(Address),  // referrer -> lifetime referral credits
+    // Global emission controls
+    GlobalDailyBudget,              // max minter-issued credits per day (0 = unlimited)
+    GlobalDailyMinted(u64),         // day -> credits issued by minters
+    MinterDailyQuota(Address),      // minter -> max credits per day (unset = unlimited)
+    MinterDailyMinted(Address, u64), // (minter, day) -> credits issued
+    EmissionAlertBps,               // usage threshold that emits an alert event
+    // Credit holds
+    Hold(BytesN<32>),               // hold_id -> CreditHold
+    UserHolds(Address),             // user -> open hold ids
+    // SEP-41 token interface
+    TransferPolicy,
+    TransferAllowlist(Address),     // contract -> may send/receive under Allowlisted policy
+    Allowance(Address, Address),    // (from, spender) -> AllowanceValue
+    // Spend sinks
+    Treasury,
+    SpendSink(u32),                 // category_id -> SpendSink
+    SpendCategoryStats(u32),        // category_id -> SpendCategoryStats
+    SinkEarnings(u32, Address),     // (category_id, recipient) -> credits routed
+    CategoryHost(u32, Address),     // (category_id, host) -> may receive Host-sink spends
 }
 
 #[contract]
@@ -119,15 +418,17 @@ impl ComputeCreditsContract {
         env.storage().instance().set(&DataKey::DailyMintLimit, &daily_mint_limit);
         env.storage().instance().set(&DataKey::PackageCounter, &0u32);
         env.storage().instance().set(&DataKey::TxCounter, &0u64);
+        env.storage().instance().set(&DataKey::GlobalDailyBudget, &0i128);
+        env.storage().instance().set(&DataKey::EmissionAlertBps, &8000u32);
         env.storage().instance().set(&DataKey::Paused, &false);
+        env.storage().instance().set(&DataKey::TransferPolicy, &TransferPolicy::Open);
+        env.storage().instance().set(&DataKey::Treasury, &admin);
+        // Category 0 backs spend_credits and captured holds
+        env.storage().persistent().set(&DataKey::SpendSink(0), &SpendSink::Burn);
         
         // Admin starts with minter/burner roles
-        let mut minters = Vec::new(&env);
-        let mut burners = Vec::new(&env);
-        minters.push_back(admin.clone());
-        burners.push_back(admin.clone());
-        env.storage().instance().set(&DataKey::MinterRole, &minters);
-        env.storage().instance().set(&DataKey::BurnerRole, &burners);
+        Self::internal_grant_role(&env, Role::Minter, &admin, 0);
+        Self::internal_grant_role(&env, Role::Burner, &admin, 0);
         
         // Create default packages
         Self::internal_create_package(&env, 100_0000000, 1_0000000, 0);
@@ -145,6 +446,17 @@ impl ComputeCreditsContract {
         Self::internal_set_activity_reward(&env, 3, 5_0000000, 0, true, 10);
         // Achievement: 10 CCC per achievement, 0s cooldown, max 5/day
         Self::internal_set_activity_reward(&env, 4, 10_0000000, 0, true, 5);
+
+        // Streaks: +10% per consecutive day, capped at +100%
+        env.storage().instance().set(&DataKey::StreakConfig, &StreakConfig {
+            bonus_bps_per_day: 1000,
+            max_bonus_bps: 10000,
+        });
+        // Referrals: 5% of referee's activity rewards for 30 days
+        env.storage().instance().set(&DataKey::ReferralConfig, &ReferralConfig {
+            reward_bps: 500,
+            duration_days: 30,
+        });
     }
 
     // === Activity Earning Functions ===
@@ -161,87 +473,33 @@ impl ComputeCreditsContract {
         Self::require_minter(&env, &minter);
         Self::require_not_paused(&env);
 
-        // Get activity reward config
-        let reward: ActivityReward = env.storage().persistent()
-            .get(&DataKey::ActivityRewardConfig(activity_type_id))
-            .expect("Activity type not configured");
-
-        if !reward.is_active {
-            panic!("Activity reward is disabled");
-        }
-
-        let now = env.ledger().timestamp();
-        let day = now / 86400;
-
-        // Check cooldown
-        if reward.cooldown_seconds > 0 {
-            let last_reward: u64 = env.storage().temporary()
-                .get(&DataKey::UserActivityCooldown(user.clone(), activity_type_id))
-                .unwrap_or(0);
-            if now - last_reward < reward.cooldown_seconds {
-                panic!("Activity on cooldown");
-            }
-        }
-
-        // Check daily cap
-        let daily_cap: u32 = env.storage().persistent()
-            .get(&DataKey::DailyActivityCap(activity_type_id))
-            .unwrap_or(100);
-        let daily_count: u32 = env.storage().temporary()
-            .get(&DataKey::UserDailyActivityCount(user.clone(), day, activity_type_id))
-            .unwrap_or(0);
-        if daily_count >= daily_cap {
-            panic!("Daily activity cap reached");
+        match Self::internal_reward_activity(&env, &minter, &user, activity_type_id, false) {
+            Ok(amount) => amount,
+            Err(status) => panic!("{}", Self::reward_status_message(&status)),
         }
+    }
 
-        // Check supply cap
-        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
-        let max_supply: i128 = env.storage().instance().get(&DataKey::MaxSupply).unwrap();
-        if total_supply + reward.reward_amount > max_supply {
-            panic!("Would exceed max supply");
-        }
+    /// Reward many (user, activity_type_id) entries in one call (minter role required).
+    /// Ineligible entries are skipped rather than failing the batch; the returned
+    /// vector holds one result per entry, in order, for server-side reconciliation.
+    pub fn reward_activities_batch(
+        env: Env,
+        minter: Address,
+        entries: Vec<(Address, u32)>,
+    ) -> Vec<BatchRewardResult> {
+        minter.require_auth();
+        Self::require_minter(&env, &minter);
+        Self::require_not_paused(&env);
 
-        // Check daily mint limit
-        let daily_mint_key = DataKey::UserDailyMint(user.clone(), day);
-        let today_minted: i128 = env.storage().temporary().get(&daily_mint_key).unwrap_or(0);
-        let daily_limit: i128 = env.storage().instance().get(&DataKey::DailyMintLimit).unwrap();
-        if today_minted + reward.reward_amount > daily_limit {
-            panic!("Would exceed daily mint limit");
+        let mut results = Vec::new(&env);
+        for (user, activity_type_id) in entries.iter() {
+            let (status, amount) = match Self::internal_reward_activity(&env, &minter, &user, activity_type_id, false) {
+                Ok(amount) => (RewardStatus::Rewarded, amount),
+                Err(status) => (status, 0),
+            };
+            results.push_back(BatchRewardResult { user, activity_type_id, status, amount });
         }
-
-        // Update trackers
-        env.storage().temporary().set(
-            &DataKey::UserActivityCooldown(user.clone(), activity_type_id),
-            &now,
-        );
-        env.storage().temporary().set(
-            &DataKey::UserDailyActivityCount(user.clone(), day, activity_type_id),
-            &(daily_count + 1),
-        );
-        env.storage().temporary().set(&daily_mint_key, &(today_minted + reward.reward_amount));
-
-        // Determine tx type based on activity
-        let tx_type = match activity_type_id {
-            0 | 3 => CreditTxType::GameReward,
-            1 => CreditTxType::RadioReward,
-            2 => CreditTxType::ChatReward,
-            _ => CreditTxType::Earn,
-        };
-
-        let description = match activity_type_id {
-            0 => String::from_str(&env, "Game play reward"),
-            1 => String::from_str(&env, "Radio listening reward"),
-            2 => String::from_str(&env, "Chat participation reward"),
-            3 => String::from_str(&env, "Game win bonus"),
-            4 => String::from_str(&env, "Achievement unlock reward"),
-            _ => String::from_str(&env, "Activity reward"),
-        };
-
-        Self::internal_credit_user(&env, &user, reward.reward_amount, tx_type, description);
-
-        env.events().publish((symbol_short!("activity"), user, activity_type_id), reward.reward_amount);
-
-        reward.reward_amount
+        results
     }
 
     /// Admin: configure an activity reward
@@ -283,6 +541,151 @@ impl ComputeCreditsContract {
             .unwrap_or(0)
     }
 
+    /// View: user's current consecutive-day streak for an activity (0 if broken)
+    pub fn get_streak(env: Env, user: Address, activity_type_id: u32) -> u32 {
+        let day = env.ledger().timestamp() / 86400;
+        let streak: Option<StreakInfo> = env.storage().persistent()
+            .get(&DataKey::UserStreak(user, activity_type_id));
+        match streak {
+            Some(s) if s.last_day + 1 >= day => s.current_streak,
+            _ => 0,
+        }
+    }
+
+    /// Admin: configure streak bonus multipliers
+    pub fn set_streak_config(env: Env, bonus_bps_per_day: u32, max_bonus_bps: u32) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::StreakConfig, &StreakConfig { bonus_bps_per_day, max_bonus_bps });
+    }
+
+    // === Proof-of-Play Functions ===
+
+    /// Commit to a play session before playing. The commitment is
+    /// sha256(session_id || score as u64 BE || duration_seconds as u64 BE).
+    pub fn commit_play_session(env: Env, user: Address, activity_type_id: u32, commitment: BytesN<32>) {
+        user.require_auth();
+        Self::require_not_paused(&env);
+
+        let key = ProofKey::Session(user.clone(), commitment.clone());
+        if env.storage().temporary().has(&key) {
+            panic!("Session already committed");
+        }
+
+        let session = PlaySession { activity_type_id, committed_at: env.ledger().timestamp() };
+        env.storage().temporary().set(&key, &session);
+
+        env.events().publish((symbol_short!("commit"), user, activity_type_id), commitment);
+    }
+
+    /// Reward an activity by revealing a committed play session (minter role required).
+    /// The reveal must hash to the commitment, meet the minimum duration, and the
+    /// claimed duration must actually have elapsed since the commitment.
+    pub fn reward_activity_with_proof(
+        env: Env,
+        minter: Address,
+        user: Address,
+        activity_type_id: u32,
+        session_id: BytesN<32>,
+        score: u64,
+        duration_seconds: u64,
+    ) -> i128 {
+        minter.require_auth();
+        Self::require_minter(&env, &minter);
+        Self::require_not_paused(&env);
+
+        let mut preimage = Bytes::from_array(&env, &session_id.to_array());
+        preimage.extend_from_array(&score.to_be_bytes());
+        preimage.extend_from_array(&duration_seconds.to_be_bytes());
+        let commitment: BytesN<32> = env.crypto().sha256(&preimage).into();
+
+        let key = ProofKey::Session(user.clone(), commitment);
+        let session: PlaySession = env.storage().temporary()
+            .get(&key)
+            .expect("Session commitment not found");
+        if session.activity_type_id != activity_type_id {
+            panic!("Session activity mismatch");
+        }
+
+        let config: ProofOfPlayConfig = env.storage().persistent()
+            .get(&ProofKey::Config(activity_type_id))
+            .unwrap_or(ProofOfPlayConfig { required: false, min_duration_seconds: 0 });
+        if duration_seconds < config.min_duration_seconds {
+            panic!("Session too short");
+        }
+        if env.ledger().timestamp() < session.committed_at + duration_seconds {
+            panic!("Session duration has not elapsed");
+        }
+
+        env.storage().temporary().remove(&key);
+
+        let amount = match Self::internal_reward_activity(&env, &minter, &user, activity_type_id, true) {
+            Ok(amount) => amount,
+            Err(status) => panic!("{}", Self::reward_status_message(&status)),
+        };
+
+        env.events().publish((symbol_short!("played"), user, session_id), score);
+
+        amount
+    }
+
+    /// Admin: require (or stop requiring) proof of play for an activity type
+    pub fn set_proof_of_play(env: Env, activity_type_id: u32, required: bool, min_duration_seconds: u64) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().persistent().set(
+            &ProofKey::Config(activity_type_id),
+            &ProofOfPlayConfig { required, min_duration_seconds },
+        );
+    }
+
+    pub fn get_proof_of_play(env: Env, activity_type_id: u32) -> ProofOfPlayConfig {
+        env.storage().persistent()
+            .get(&ProofKey::Config(activity_type_id))
+            .unwrap_or(ProofOfPlayConfig { required: false, min_duration_seconds: 0 })
+    }
+
+    // === Referral Functions ===
+
+    /// Register the referrer for a user (once per user)
+    pub fn register_referrer(env: Env, user: Address, referrer: Address) {
+        user.require_auth();
+        Self::require_not_paused(&env);
+
+        if user == referrer {
+            panic!("Cannot refer yourself");
+        }
+        let key = DataKey::Referral(user.clone());
+        if env.storage().persistent().has(&key) {
+            panic!("Referrer already registered");
+        }
+
+        let referral = Referral { referrer: referrer.clone(), registered_at: env.ledger().timestamp() };
+        env.storage().persistent().set(&key, &referral);
+
+        env.events().publish((symbol_short!("referral"), user), referrer);
+    }
+
+    /// Admin: configure referral payout share and duration
+    pub fn set_referral_config(env: Env, reward_bps: u32, duration_days: u64) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if reward_bps > 10000 {
+            panic!("Invalid reward bps");
+        }
+        env.storage().instance().set(&DataKey::ReferralConfig, &ReferralConfig { reward_bps, duration_days });
+    }
+
+    /// View: get a user's referral relationship
+    pub fn get_referral(env: Env, user: Address) -> Option<Referral> {
+        env.storage().persistent().get(&DataKey::Referral(user))
+    }
+
+    /// View: lifetime credits a referrer has earned from referrals
+    pub fn get_referral_earnings(env: Env, referrer: Address) -> i128 {
+        env.storage().persistent().get(&DataKey::ReferralEarnings(referrer)).unwrap_or(0)
+    }
+
     // === Purchase Functions ===
 
     /// Buy credits with USDC
@@ -298,13 +701,28 @@ impl ComputeCreditsContract {
             panic!("Package is not active");
         }
         
-        let total_credits = package.credits + package.bonus_credits;
+        let mut total_credits = package.credits + package.bonus_credits;
+
+        // Apply the first live promotion the buyer is still eligible for
+        let promotion = Self::find_promotion(&env, package_id, &buyer);
+        if let Some(promo) = promotion.clone() {
+            total_credits += total_credits * promo.bonus_bps as i128 / 10000;
+        }
         
         let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
         let max_supply: i128 = env.storage().instance().get(&DataKey::MaxSupply).unwrap();
         if total_supply + total_credits > max_supply {
             panic!("Would exceed max supply");
         }
+
+        if let Some(mut promo) = promotion {
+            promo.sold_count += 1;
+            env.storage().persistent().set(&PromotionKey::Promotion(promo.id), &promo);
+            let purchases_key = PromotionKey::UserPurchases(promo.id, buyer.clone());
+            let purchases: u32 = env.storage().persistent().get(&purchases_key).unwrap_or(0);
+            env.storage().persistent().set(&purchases_key, &(purchases + 1));
+            env.events().publish((symbol_short!("promo"), buyer.clone(), promo.id), total_credits);
+        }
         
         let usdc: Address = env.storage().instance().get(&DataKey::USDCToken).unwrap();
         let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
@@ -317,13 +735,127 @@ impl ComputeCreditsContract {
         env.events().publish((symbol_short!("buy"), buyer), total_credits);
     }
 
-    // === Earn/Spend Functions ===
-
-    /// Award credits (minter role required)
-    pub fn award_credits(
+    /// Redeem a signed receipt for an off-chain (card) purchase.
+    /// The payment oracle signs the XDR of (contract, user, package_id, receipt_id);
+    /// each receipt_id can only be redeemed once.
+    pub fn redeem_purchase_receipt(
         env: Env,
-        minter: Address,
-        recipient: Address,
+        user: Address,
+        package_id: u32,
+        receipt_id: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> i128 {
+        Self::require_not_paused(&env);
+
+        let oracle_key: BytesN<32> = env.storage().instance()
+            .get(&DataKey::PaymentOracle)
+            .expect("Payment oracle not set");
+
+        let receipt_key = DataKey::RedeemedReceipt(receipt_id.clone());
+        if env.storage().persistent().has(&receipt_key) {
+            panic!("Receipt already redeemed");
+        }
+
+        let message = (
+            env.current_contract_address(),
+            user.clone(),
+            package_id,
+            receipt_id.clone(),
+        ).to_xdr(&env);
+        env.crypto().ed25519_verify(&oracle_key, &message, &signature);
+
+        // The card payment already settled off-chain, so the receipt is honoured
+        // even if the package has since been deactivated.
+        let package: CreditPackage = env.storage().persistent()
+            .get(&DataKey::CreditPackage(package_id))
+            .expect("Package not found");
+
+        let total_credits = package.credits + package.bonus_credits;
+
+        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
+        let max_supply: i128 = env.storage().instance().get(&DataKey::MaxSupply).unwrap();
+        if total_supply + total_credits > max_supply {
+            panic!("Would exceed max supply");
+        }
+
+        env.storage().persistent().set(&receipt_key, &true);
+
+        Self::internal_credit_user(&env, &user, total_credits, CreditTxType::Purchase,
+            String::from_str(&env, "Card purchase receipt"));
+
+        env.events().publish((symbol_short!("receipt"), user, receipt_id), total_credits);
+
+        total_credits
+    }
+
+    /// View: whether a purchase receipt has already been redeemed
+    pub fn is_receipt_redeemed(env: Env, receipt_id: BytesN<32>) -> bool {
+        env.storage().persistent().has(&DataKey::RedeemedReceipt(receipt_id))
+    }
+
+    /// Redeem a voucher code for credits (once per code)
+    pub fn redeem_voucher(env: Env, user: Address, code: Bytes) -> i128 {
+        user.require_auth();
+        Self::require_not_paused(&env);
+
+        let code_hash: BytesN<32> = env.crypto().sha256(&code).into();
+        let voucher_key = VoucherKey::Voucher(code_hash.clone());
+        let mut voucher: Voucher = env.storage().persistent().get(&voucher_key).expect("Invalid voucher");
+        if voucher.is_redeemed {
+            panic!("Voucher already redeemed");
+        }
+        if voucher.is_revoked {
+            panic!("Voucher revoked");
+        }
+
+        let batch_key = VoucherKey::Batch(voucher.batch_id);
+        let mut batch: VoucherBatch = env.storage().persistent().get(&batch_key).unwrap();
+        if batch.is_revoked {
+            panic!("Voucher batch revoked");
+        }
+        if env.ledger().timestamp() >= batch.expiry {
+            panic!("Voucher expired");
+        }
+        if batch.redeemed_count >= batch.max_redemptions {
+            panic!("Voucher batch fully redeemed");
+        }
+
+        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
+        let max_supply: i128 = env.storage().instance().get(&DataKey::MaxSupply).unwrap();
+        if total_supply + voucher.amount > max_supply {
+            panic!("Would exceed max supply");
+        }
+
+        voucher.is_redeemed = true;
+        env.storage().persistent().set(&voucher_key, &voucher);
+        batch.redeemed_count += 1;
+        batch.redeemed_credits += voucher.amount;
+        env.storage().persistent().set(&batch_key, &batch);
+
+        Self::internal_credit_user(&env, &user, voucher.amount, CreditTxType::Voucher,
+            String::from_str(&env, "Voucher redeemed"));
+
+        env.events().publish((symbol_short!("voucher"), user, voucher.batch_id), voucher.amount);
+
+        voucher.amount
+    }
+
+    pub fn get_voucher_batch(env: Env, batch_id: u32) -> VoucherBatch {
+        env.storage().persistent().get(&VoucherKey::Batch(batch_id)).expect("Voucher batch not found")
+    }
+
+    /// View: look up a voucher by sha256(code)
+    pub fn get_voucher(env: Env, code_hash: BytesN<32>) -> Option<Voucher> {
+        env.storage().persistent().get(&VoucherKey::Voucher(code_hash))
+    }
+
+    // === Earn/Spend Functions ===
+
+    /// Award credits (minter role required)
+    pub fn award_credits(
+        env: Env,
+        minter: Address,
+        recipient: Address,
         amount: i128,
         description: String,
     ) {
@@ -345,15 +877,20 @@ impl ComputeCreditsContract {
         if total_supply + amount > max_supply {
             panic!("Would exceed max supply");
         }
+
+        if let Err(status) = Self::check_emission(&env, &minter, day, amount) {
+            panic!("{}", Self::reward_status_message(&status));
+        }
         
         env.storage().temporary().set(&daily_key, &(today_minted + amount));
+        Self::record_emission(&env, &minter, day, amount);
         
         Self::internal_credit_user(&env, &recipient, amount, CreditTxType::Earn, description);
         
         env.events().publish((symbol_short!("award"), recipient), amount);
     }
 
-    /// Spend credits
+    /// Spend credits (default category 0)
     pub fn spend_credits(
         env: Env,
         user: Address,
@@ -363,11 +900,30 @@ impl ComputeCreditsContract {
         user.require_auth();
         Self::require_not_paused(&env);
         
-        Self::internal_debit_user(&env, &user, amount, CreditTxType::Spend, description);
+        Self::internal_spend(&env, &user, 0, amount, None, description);
         
         env.events().publish((symbol_short!("spend"), user), amount);
     }
 
+    /// Spend credits in a category; the category's sink decides whether they are
+    /// burned or routed. `SpendSink::Host` categories require a `beneficiary` that is
+    /// registered as a host on the category and is not the spender.
+    pub fn spend_in_category(
+        env: Env,
+        user: Address,
+        category_id: u32,
+        amount: i128,
+        beneficiary: Option<Address>,
+        description: String,
+    ) {
+        user.require_auth();
+        Self::require_not_paused(&env);
+
+        Self::internal_spend(&env, &user, category_id, amount, beneficiary, description);
+
+        env.events().publish((symbol_short!("spend"), user, category_id), amount);
+    }
+
     /// Transfer credits between users
     pub fn transfer_credits(
         env: Env,
@@ -382,10 +938,7 @@ impl ComputeCreditsContract {
             panic!("Amount must be positive");
         }
         
-        Self::internal_debit_user(&env, &from, amount, CreditTxType::Transfer, 
-            String::from_str(&env, "Transfer out"));
-        Self::internal_credit_user(&env, &to, amount, CreditTxType::Transfer, 
-            String::from_str(&env, "Transfer in"));
+        Self::internal_transfer(&env, &from, &to, amount);
         
         env.events().publish((symbol_short!("transfer"), from, to), amount);
     }
@@ -405,212 +958,2174 @@ impl ComputeCreditsContract {
         env.events().publish((symbol_short!("burn"), user), amount);
     }
 
-    // === Admin Functions ===
+    // === SEP-41 Token Interface ===
 
-    pub fn create_package(env: Env, credits: i128, price_usdc: i128, bonus_credits: i128) -> u32 {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        Self::internal_create_package(&env, credits, price_usdc, bonus_credits)
+    pub fn name(env: Env) -> String {
+        String::from_str(&env, "Compute Credits")
     }
 
-    pub fn update_package(env: Env, package_id: u32, credits: i128, price_usdc: i128, bonus_credits: i128, is_active: bool) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        let package = CreditPackage { id: package_id, credits, price_usdc, bonus_credits, is_active };
-        env.storage().persistent().set(&DataKey::CreditPackage(package_id), &package);
+    pub fn symbol(env: Env) -> String {
+        String::from_str(&env, "CCC")
     }
 
-    pub fn add_minter(env: Env, minter: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        let mut minters: Vec<Address> = env.storage().instance().get(&DataKey::MinterRole).unwrap();
-        minters.push_back(minter.clone());
-        env.storage().instance().set(&DataKey::MinterRole, &minters);
-        env.events().publish((symbol_short!("minter"),), minter);
+    pub fn decimals(_env: Env) -> u32 {
+        7
     }
 
-    pub fn remove_minter(env: Env, minter: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        let minters: Vec<Address> = env.storage().instance().get(&DataKey::MinterRole).unwrap();
-        let mut new_minters = Vec::new(&env);
-        for i in 0..minters.len() {
-            let m = minters.get(i).unwrap();
-            if m != minter { new_minters.push_back(m); }
-        }
-        env.storage().instance().set(&DataKey::MinterRole, &new_minters);
+    /// Spendable balance: credits under active holds are excluded, since they
+    /// cannot be transferred. Use `get_balance` for the gross amount.
+    pub fn balance(env: Env, id: Address) -> i128 {
+        Self::spendable_balance(&env, &id)
     }
 
-    pub fn add_burner(env: Env, burner: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        let mut burners: Vec<Address> = env.storage().instance().get(&DataKey::BurnerRole).unwrap();
-        burners.push_back(burner.clone());
-        env.storage().instance().set(&DataKey::BurnerRole, &burners);
+    pub fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+        Self::read_allowance(&env, &from, &spender).amount
     }
 
-    pub fn set_limits(env: Env, max_supply: i128, daily_mint_limit: i128) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        env.storage().instance().set(&DataKey::MaxSupply, &max_supply);
-        env.storage().instance().set(&DataKey::DailyMintLimit, &daily_mint_limit);
-    }
+    pub fn approve(env: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        from.require_auth();
 
-    pub fn rotate_admin(env: Env, new_admin: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        env.storage().instance().set(&DataKey::Admin, &new_admin);
-        env.events().publish((symbol_short!("admin"),), new_admin);
-    }
+        if amount < 0 {
+            panic!("Amount cannot be negative");
+        }
+        if amount > 0 && expiration_ledger < env.ledger().sequence() {
+            panic!("Expiration ledger is in the past");
+        }
 
-    pub fn set_paused(env: Env, paused: bool) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
-        env.storage().instance().set(&DataKey::Paused, &paused);
+        let key = DataKey::Allowance(from.clone(), spender.clone());
+        env.storage().temporary().set(&key, &AllowanceValue { amount, expiration_ledger });
+        if amount > 0 {
+            let live_for = expiration_ledger - env.ledger().sequence();
+            env.storage().temporary().extend_ttl(&key, live_for, live_for);
+        }
+
+        TokenUtils::new(&env).events().approve(from, spender, amount, expiration_ledger);
     }
 
-    // === View Functions ===
+    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+        Self::require_not_paused(&env);
 
-    pub fn get_balance(env: Env, user: Address) -> i128 {
-        env.storage().persistent()
-            .get::<_, UserCredits>(&DataKey::UserCredits(user))
-            .map(|c| c.balance)
-            .unwrap_or(0)
-    }
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
 
-    pub fn get_user_credits(env: Env, user: Address) -> UserCredits {
-        env.storage().persistent()
-            .get(&DataKey::UserCredits(user))
-            .unwrap_or(UserCredits {
-                balance: 0, lifetime_earned: 0, lifetime_spent: 0, last_activity: 0,
-            })
-    }
+        Self::internal_transfer(&env, &from, &to, amount);
 
-    pub fn get_package(env: Env, package_id: u32) -> CreditPackage {
-        env.storage().persistent().get(&DataKey::CreditPackage(package_id)).expect("Package not found")
+        TokenUtils::new(&env).events().transfer(from, to, amount);
     }
 
-    pub fn get_all_packages(env: Env) -> Vec<CreditPackage> {
-        let counter: u32 = env.storage().instance().get(&DataKey::PackageCounter).unwrap_or(0);
-        let mut packages = Vec::new(&env);
-        for i in 1..=counter {
-            if let Some(pkg) = env.storage().persistent().get::<_, CreditPackage>(&DataKey::CreditPackage(i)) {
-                packages.push_back(pkg);
-            }
+    pub fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+        Self::require_not_paused(&env);
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
         }
-        packages
-    }
 
-    pub fn get_total_supply(env: Env) -> i128 {
-        env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0)
+        Self::spend_allowance(&env, &from, &spender, amount);
+        Self::internal_transfer(&env, &from, &to, amount);
+
+        TokenUtils::new(&env).events().transfer(from, to, amount);
     }
 
-    pub fn get_max_supply(env: Env) -> i128 {
-        env.storage().instance().get(&DataKey::MaxSupply).unwrap_or(0)
+    pub fn burn(env: Env, from: Address, amount: i128) {
+        from.require_auth();
+        Self::require_not_paused(&env);
+
+        Self::internal_burn(&env, &from, amount);
+
+        TokenUtils::new(&env).events().burn(from, amount);
     }
 
-    pub fn is_paused(env: Env) -> bool {
-        env.storage().instance().get(&DataKey::Paused).unwrap_or(false)
+    pub fn burn_from(env: Env, spender: Address, from: Address, amount: i128) {
+        spender.require_auth();
+        Self::require_not_paused(&env);
+
+        Self::spend_allowance(&env, &from, &spender, amount);
+        Self::internal_burn(&env, &from, amount);
+
+        TokenUtils::new(&env).events().burn(from, amount);
     }
 
-    // === Internal Functions ===
+    // === Subscription Functions ===
 
-    fn internal_set_activity_reward(env: &Env, activity_type_id: u32, reward_amount: i128, cooldown_seconds: u64, is_active: bool, daily_cap: u32) {
-        let activity_type = match activity_type_id {
-            0 => ActivityType::GamePlay,
-            1 => ActivityType::RadioListen,
-            2 => ActivityType::ChatMessage,
-            3 => ActivityType::GameWin,
-            4 => ActivityType::Achievement,
-            _ => panic!("Invalid activity type"),
-        };
+    /// Subscribe to a plan, paying the first period immediately
+    pub fn subscribe(env: Env, user: Address, plan_id: u32) {
+        user.require_auth();
+        Self::require_not_paused(&env);
 
-        let reward = ActivityReward {
-            activity_type,
-            reward_amount,
-            cooldown_seconds,
-            is_active,
+        let plan = Self::get_plan(env.clone(), plan_id);
+        if !plan.is_active {
+            panic!("Plan is not active");
+        }
+        if Self::is_subscribed(env.clone(), user.clone(), plan_id) {
+            panic!("Already subscribed");
+        }
+
+        Self::internal_spend(&env, &user, plan.spend_category, plan.price, None,
+            String::from_str(&env, "Subscription payment"));
+
+        let now = env.ledger().timestamp();
+        let subscription = Subscription {
+            plan_id,
+            started_at: now,
+            paid_until: now + plan.period_seconds,
+            status: SubscriptionStatus::Active,
         };
+        env.storage().persistent().set(&SubscriptionKey::Subscription(user.clone(), plan_id), &subscription);
 
-        env.storage().persistent().set(&DataKey::ActivityRewardConfig(activity_type_id), &reward);
-        env.storage().persistent().set(&DataKey::DailyActivityCap(activity_type_id), &daily_cap);
+        env.events().publish((symbol_short!("subscribe"), user, plan_id), plan.price);
     }
 
-    fn internal_create_package(env: &Env, credits: i128, price_usdc: i128, bonus_credits: i128) -> u32 {
-        let counter: u32 = env.storage().instance().get(&DataKey::PackageCounter).unwrap_or(0);
-        let new_id = counter + 1;
-        let package = CreditPackage { id: new_id, credits, price_usdc, bonus_credits, is_active: true };
-        env.storage().persistent().set(&DataKey::CreditPackage(new_id), &package);
-        env.storage().instance().set(&DataKey::PackageCounter, &new_id);
-        new_id
-    }
+    /// Stop future charges; access continues until the paid period ends
+    pub fn unsubscribe(env: Env, user: Address, plan_id: u32) {
+        user.require_auth();
 
-    fn internal_credit_user(env: &Env, user: &Address, amount: i128, tx_type: CreditTxType, description: String) {
-        let mut credits: UserCredits = env.storage().persistent()
-            .get(&DataKey::UserCredits(user.clone()))
-            .unwrap_or(UserCredits {
-                balance: 0, lifetime_earned: 0, lifetime_spent: 0, last_activity: 0,
-            });
-        
-        credits.balance += amount;
-        credits.lifetime_earned += amount;
-        credits.last_activity = env.ledger().timestamp();
-        
-        env.storage().persistent().set(&DataKey::UserCredits(user.clone()), &credits);
-        
-        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0);
-        env.storage().instance().set(&DataKey::TotalSupply, &(total_supply + amount));
-        
-        Self::record_transaction(env, user.clone(), amount, tx_type, description);
+        let key = SubscriptionKey::Subscription(user.clone(), plan_id);
+        let mut subscription: Subscription = env.storage().persistent().get(&key).expect("Not subscribed");
+        if subscription.status != SubscriptionStatus::Active {
+            panic!("Subscription is not active");
+        }
+        subscription.status = SubscriptionStatus::Cancelled;
+        env.storage().persistent().set(&key, &subscription);
+
+        env.events().publish((symbol_short!("unsub"), user, plan_id), subscription.paid_until);
     }
 
-    fn internal_debit_user(env: &Env, user: &Address, amount: i128, tx_type: CreditTxType, description: String) {
-        let mut credits: UserCredits = env.storage().persistent()
-            .get(&DataKey::UserCredits(user.clone()))
-            .expect("User has no credits");
-        
-        if credits.balance < amount {
-            panic!("Insufficient credits");
+    /// Keeper entry point: collect the next period's payment if it is due.
+    /// A payment that cannot be collected (insufficient credits, a retired
+    /// plan, or a category since moved to a Host sink) marks the subscription
+    /// expired instead of failing.
+    pub fn charge_due(env: Env, user: Address, plan_id: u32) -> SubscriptionStatus {
+        Self::require_not_paused(&env);
+
+        let key = SubscriptionKey::Subscription(user.clone(), plan_id);
+        let mut subscription: Subscription = env.storage().persistent().get(&key).expect("Not subscribed");
+        if subscription.status != SubscriptionStatus::Active {
+            return subscription.status;
         }
-        
-        credits.balance -= amount;
-        credits.lifetime_spent += amount;
-        credits.last_activity = env.ledger().timestamp();
-        
-        env.storage().persistent().set(&DataKey::UserCredits(user.clone()), &credits);
-        
-        Self::record_transaction(env, user.clone(), -amount, tx_type, description);
-    }
 
-    fn record_transaction(env: &Env, user: Address, amount: i128, tx_type: CreditTxType, description: String) {
-        let counter: u64 = env.storage().instance().get(&DataKey::TxCounter).unwrap_or(0);
-        let new_id = counter + 1;
-        let tx = CreditTransaction { id: new_id, user, amount, tx_type, description, timestamp: env.ledger().timestamp() };
-        env.storage().persistent().set(&DataKey::Transaction(new_id), &tx);
-        env.storage().instance().set(&DataKey::TxCounter, &new_id);
-    }
+        let now = env.ledger().timestamp();
+        if now < subscription.paid_until {
+            return SubscriptionStatus::Active;
+        }
 
-    fn require_not_paused(env: &Env) {
-        if env.storage().instance().get(&DataKey::Paused).unwrap_or(false) {
-            panic!("Contract is paused");
+        let plan = Self::get_plan(env.clone(), plan_id);
+        let collectable = plan.is_active
+            && Self::get_spend_sink(env.clone(), plan.spend_category) != SpendSink::Host
+            && Self::spendable_balance(&env, &user) >= plan.price;
+        if !collectable {
+            subscription.status = SubscriptionStatus::Expired;
+            env.storage().persistent().set(&key, &subscription);
+            env.events().publish((symbol_short!("sub_lapse"), user, plan_id), subscription.paid_until);
+            return SubscriptionStatus::Expired;
         }
+
+        Self::internal_spend(&env, &user, plan.spend_category, plan.price, None,
+            String::from_str(&env, "Subscription renewal"));
+
+        // Periods missed by the keeper are not back-charged
+        subscription.paid_until = (subscription.paid_until + plan.period_seconds).max(now + plan.period_seconds);
+        env.storage().persistent().set(&key, &subscription);
+
+        env.events().publish((symbol_short!("sub_renew"), user, plan_id), plan.price);
+
+        SubscriptionStatus::Active
     }
 
-    fn require_minter(env: &Env, addr: &Address) {
-        let minters: Vec<Address> = env.storage().instance().get(&DataKey::MinterRole).unwrap();
-        let mut is_minter = false;
-        for i in 0..minters.len() {
-            if &minters.get(i).unwrap() == addr { is_minter = true; break; }
+    /// View: whether the user currently has paid access to a plan
+    pub fn is_subscribed(env: Env, user: Address, plan_id: u32) -> bool {
+        let subscription: Option<Subscription> = env.storage().persistent()
+            .get(&SubscriptionKey::Subscription(user, plan_id));
+        match subscription {
+            Some(s) => s.status != SubscriptionStatus::Expired && env.ledger().timestamp() < s.paid_until,
+            None => false,
         }
-        if !is_minter { panic!("Not a minter"); }
     }
 
-    fn require_burner(env: &Env, addr: &Address) {
-        let burners: Vec<Address> = env.storage().instance().get(&DataKey::BurnerRole).unwrap();
-        let mut is_burner = false;
-        for i in 0..burners.len() {
-            if &burners.get(i).unwrap() == addr { is_burner = true; break; }
-        }
-        if !is_burner { panic!("Not a burner"); }
+    pub fn get_subscription(env: Env, user: Address, plan_id: u32) -> Option<Subscription> {
+        env.storage().persistent().get(&SubscriptionKey::Subscription(user, plan_id))
     }
-}
\ No newline at end of file
+
+    pub fn get_plan(env: Env, plan_id: u32) -> SubscriptionPlan {
+        env.storage().persistent().get(&SubscriptionKey::Plan(plan_id)).expect("Plan not found")
+    }
+
+    // === Hold Functions ===
+
+    /// Place a reversible hold on a user's credits (hold operator required).
+    /// Held credits stay in the balance but cannot be spent until the hold is
+    /// captured or released; an uncaptured hold lapses automatically at `expiry`.
+    pub fn hold_credits(
+        env: Env,
+        operator: Address,
+        user: Address,
+        amount: i128,
+        hold_id: BytesN<32>,
+        expiry: u64,
+    ) {
+        operator.require_auth();
+        user.require_auth();
+        Self::require_hold_operator(&env, &operator);
+        Self::require_not_paused(&env);
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if expiry <= env.ledger().timestamp() {
+            panic!("Expiry must be in the future");
+        }
+        if env.storage().persistent().has(&DataKey::Hold(hold_id.clone())) {
+            panic!("Hold already exists");
+        }
+        if Self::spendable_balance(&env, &user) < amount {
+            panic!("Insufficient credits");
+        }
+
+        let hold = CreditHold {
+            id: hold_id.clone(),
+            user: user.clone(),
+            operator,
+            amount,
+            expiry,
+            status: HoldStatus::Active,
+        };
+        env.storage().persistent().set(&DataKey::Hold(hold_id.clone()), &hold);
+
+        let mut open_holds = Self::prune_holds(&env, &user);
+        open_holds.push_back(hold_id.clone());
+        env.storage().persistent().set(&DataKey::UserHolds(user.clone()), &open_holds);
+
+        env.events().publish((symbol_short!("hold"), user, hold_id), amount);
+    }
+
+    /// Capture an active hold, spending the held credits. Only the operator
+    /// that placed the hold (or the admin) may capture it.
+    pub fn capture_hold(env: Env, operator: Address, hold_id: BytesN<32>) {
+        operator.require_auth();
+        Self::require_not_paused(&env);
+
+        let mut hold = Self::get_hold(env.clone(), hold_id.clone());
+        Self::require_hold_owner(&env, &operator, &hold);
+        if hold.status != HoldStatus::Active {
+            panic!("Hold is not active");
+        }
+        if env.ledger().timestamp() >= hold.expiry {
+            panic!("Hold has expired");
+        }
+
+        hold.status = HoldStatus::Captured;
+        env.storage().persistent().set(&DataKey::Hold(hold_id.clone()), &hold);
+
+        Self::internal_spend(&env, &hold.user, 0, hold.amount, None,
+            String::from_str(&env, "Hold captured"));
+
+        env.events().publish((symbol_short!("capture"), hold.user, hold_id), hold.amount);
+    }
+
+    /// Release a hold without spending. The operator that placed it (or the
+    /// admin) may release at any time; once expired, anyone may release it to
+    /// tidy up state.
+    pub fn release_hold(env: Env, operator: Address, hold_id: BytesN<32>) {
+        operator.require_auth();
+
+        let mut hold = Self::get_hold(env.clone(), hold_id.clone());
+        if hold.status != HoldStatus::Active {
+            panic!("Hold is not active");
+        }
+        if env.ledger().timestamp() < hold.expiry {
+            Self::require_hold_owner(&env, &operator, &hold);
+        }
+
+        hold.status = HoldStatus::Released;
+        env.storage().persistent().set(&DataKey::Hold(hold_id.clone()), &hold);
+
+        env.events().publish((symbol_short!("release"), hold.user, hold_id), hold.amount);
+    }
+
+    // === Admin Functions ===
+
+    pub fn create_package(env: Env, credits: i128, price_usdc: i128, bonus_credits: i128) -> u32 {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        Self::internal_create_package(&env, credits, price_usdc, bonus_credits)
+    }
+
+    /// Admin: schedule a promotion on a package; the bonus is capped at 100%
+    pub fn create_promotion(
+        env: Env,
+        package_id: u32,
+        start_time: u64,
+        end_time: u64,
+        bonus_bps: u32,
+        per_user_limit: u32,
+        global_cap: u32,
+    ) -> u32 {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if !env.storage().persistent().has(&DataKey::CreditPackage(package_id)) {
+            panic!("Package not found");
+        }
+        if end_time <= start_time || end_time <= env.ledger().timestamp() {
+            panic!("Invalid promotion window");
+        }
+        if bonus_bps > 10000 {
+            panic!("Invalid bonus bps");
+        }
+
+        let counter: u32 = env.storage().instance().get(&PromotionKey::Counter).unwrap_or(0);
+        let new_id = counter + 1;
+        let promo = Promotion {
+            id: new_id,
+            package_id,
+            start_time,
+            end_time,
+            bonus_bps,
+            per_user_limit,
+            global_cap,
+            sold_count: 0,
+            is_active: true,
+        };
+        env.storage().persistent().set(&PromotionKey::Promotion(new_id), &promo);
+        env.storage().instance().set(&PromotionKey::Counter, &new_id);
+
+        let mut package_promos: Vec<u32> = env.storage().persistent()
+            .get(&PromotionKey::PackagePromotions(package_id))
+            .unwrap_or(Vec::new(&env));
+        package_promos.push_back(new_id);
+        env.storage().persistent().set(&PromotionKey::PackagePromotions(package_id), &package_promos);
+
+        env.events().publish((symbol_short!("promo_new"), package_id), new_id);
+        new_id
+    }
+
+    pub fn cancel_promotion(env: Env, promotion_id: u32) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        let mut promo = Self::get_promotion(env.clone(), promotion_id);
+        promo.is_active = false;
+        env.storage().persistent().set(&PromotionKey::Promotion(promotion_id), &promo);
+    }
+
+    pub fn update_package(env: Env, package_id: u32, credits: i128, price_usdc: i128, bonus_credits: i128, is_active: bool) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        let package = CreditPackage { id: package_id, credits, price_usdc, bonus_credits, is_active };
+        env.storage().persistent().set(&DataKey::CreditPackage(package_id), &package);
+    }
+
+    /// Admin: grant a role, optionally expiring at `expires_at` (0 = never).
+    /// Re-granting an existing member only updates the expiry.
+    pub fn grant_role(env: Env, role: Role, account: Address, expires_at: u64) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        Self::internal_grant_role(&env, role, &account, expires_at);
+    }
+
+    /// Admin: revoke a role (no-op if the account does not hold it)
+    pub fn revoke_role(env: Env, role: Role, account: Address) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        Self::internal_revoke_role(&env, role, &account);
+    }
+
+    /// Admin: create a voucher batch with an expiry and a cap on total redemptions
+    pub fn create_voucher_batch(env: Env, expiry: u64, max_redemptions: u32) -> u32 {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if expiry <= env.ledger().timestamp() {
+            panic!("Expiry must be in the future");
+        }
+        let counter: u32 = env.storage().instance().get(&VoucherKey::BatchCounter).unwrap_or(0);
+        let new_id = counter + 1;
+        let batch = VoucherBatch {
+            id: new_id,
+            expiry,
+            max_redemptions,
+            voucher_count: 0,
+            redeemed_count: 0,
+            redeemed_credits: 0,
+            is_revoked: false,
+        };
+        env.storage().persistent().set(&VoucherKey::Batch(new_id), &batch);
+        env.storage().instance().set(&VoucherKey::BatchCounter, &new_id);
+        new_id
+    }
+
+    /// Admin: add (sha256(code), amount) vouchers to a batch
+    pub fn add_vouchers(env: Env, batch_id: u32, vouchers: Vec<(BytesN<32>, i128)>) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        let mut batch = Self::get_voucher_batch(env.clone(), batch_id);
+        if batch.is_revoked {
+            panic!("Voucher batch revoked");
+        }
+        for (code_hash, amount) in vouchers.iter() {
+            if amount <= 0 {
+                panic!("Amount must be positive");
+            }
+            let key = VoucherKey::Voucher(code_hash);
+            if env.storage().persistent().has(&key) {
+                panic!("Voucher already exists");
+            }
+            env.storage().persistent().set(&key, &Voucher { batch_id, amount, is_redeemed: false, is_revoked: false });
+            batch.voucher_count += 1;
+        }
+        env.storage().persistent().set(&VoucherKey::Batch(batch_id), &batch);
+    }
+
+    pub fn revoke_voucher_batch(env: Env, batch_id: u32) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        let mut batch = Self::get_voucher_batch(env.clone(), batch_id);
+        batch.is_revoked = true;
+        env.storage().persistent().set(&VoucherKey::Batch(batch_id), &batch);
+        env.events().publish((symbol_short!("vch_revok"), batch_id), batch.redeemed_count);
+    }
+
+    pub fn revoke_voucher(env: Env, code_hash: BytesN<32>) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        let key = VoucherKey::Voucher(code_hash);
+        let mut voucher: Voucher = env.storage().persistent().get(&key).expect("Invalid voucher");
+        voucher.is_revoked = true;
+        env.storage().persistent().set(&key, &voucher);
+    }
+
+    pub fn create_plan(env: Env, name: String, price: i128, period_seconds: u64, spend_category: u32) -> u32 {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if price <= 0 || period_seconds == 0 {
+            panic!("Invalid plan");
+        }
+        // Renewals are keeper-driven and carry no beneficiary
+        if Self::get_spend_sink(env.clone(), spend_category) == SpendSink::Host {
+            panic!("Plans cannot use a Host-sink category");
+        }
+        let counter: u32 = env.storage().instance().get(&SubscriptionKey::PlanCounter).unwrap_or(0);
+        let new_id = counter + 1;
+        let plan = SubscriptionPlan { id: new_id, name, price, period_seconds, spend_category, is_active: true };
+        env.storage().persistent().set(&SubscriptionKey::Plan(new_id), &plan);
+        env.storage().instance().set(&SubscriptionKey::PlanCounter, &new_id);
+        new_id
+    }
+
+    pub fn update_plan(env: Env, plan_id: u32, price: i128, period_seconds: u64, is_active: bool) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if price <= 0 || period_seconds == 0 {
+            panic!("Invalid plan");
+        }
+        let mut plan = Self::get_plan(env.clone(), plan_id);
+        plan.price = price;
+        plan.period_seconds = period_seconds;
+        plan.is_active = is_active;
+        env.storage().persistent().set(&SubscriptionKey::Plan(plan_id), &plan);
+    }
+
+    pub fn add_minter(env: Env, minter: Address) {
+        Self::grant_role(env, Role::Minter, minter, 0);
+    }
+
+    pub fn remove_minter(env: Env, minter: Address) {
+        Self::revoke_role(env, Role::Minter, minter);
+    }
+
+    pub fn add_burner(env: Env, burner: Address) {
+        Self::grant_role(env, Role::Burner, burner, 0);
+    }
+
+    pub fn remove_burner(env: Env, burner: Address) {
+        Self::revoke_role(env, Role::Burner, burner);
+    }
+
+    /// Admin: set the ed25519 public key that signs off-chain purchase receipts
+    pub fn set_payment_oracle(env: Env, oracle_key: BytesN<32>) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::PaymentOracle, &oracle_key);
+        env.events().publish((symbol_short!("oracle"),), oracle_key);
+    }
+
+    /// Admin: cap total minter-issued credits per day across all users (0 = unlimited)
+    pub fn set_emission_budget(env: Env, daily_budget: i128, alert_bps: u32) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if daily_budget < 0 || alert_bps > 10000 {
+            panic!("Invalid emission budget");
+        }
+        env.storage().instance().set(&DataKey::GlobalDailyBudget, &daily_budget);
+        env.storage().instance().set(&DataKey::EmissionAlertBps, &alert_bps);
+    }
+
+    /// Admin: cap credits a single minter may issue per day
+    pub fn set_minter_quota(env: Env, minter: Address, daily_quota: i128) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if daily_quota < 0 {
+            panic!("Invalid minter quota");
+        }
+        env.storage().persistent().set(&DataKey::MinterDailyQuota(minter.clone()), &daily_quota);
+        env.events().publish((symbol_short!("quota"), minter), daily_quota);
+    }
+
+    /// Admin: remove a minter's daily quota
+    pub fn clear_minter_quota(env: Env, minter: Address) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().persistent().remove(&DataKey::MinterDailyQuota(minter));
+    }
+
+    pub fn add_hold_operator(env: Env, operator: Address) {
+        Self::grant_role(env, Role::HoldOperator, operator, 0);
+    }
+
+    pub fn remove_hold_operator(env: Env, operator: Address) {
+        Self::revoke_role(env, Role::HoldOperator, operator);
+    }
+
+    pub fn set_transfer_policy(env: Env, policy: TransferPolicy) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::TransferPolicy, &policy);
+        env.events().publish((symbol_short!("xfer_pol"),), policy);
+    }
+
+    /// Admin: allow a contract to send/receive credits under the Allowlisted policy
+    pub fn set_transfer_allowlisted(env: Env, contract: Address, allowed: bool) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if allowed {
+            env.storage().persistent().set(&DataKey::TransferAllowlist(contract.clone()), &true);
+        } else {
+            env.storage().persistent().remove(&DataKey::TransferAllowlist(contract.clone()));
+        }
+        env.events().publish((symbol_short!("xfer_list"), contract), allowed);
+    }
+
+    pub fn set_treasury(env: Env, treasury: Address) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::Treasury, &treasury);
+    }
+
+    /// Admin: configure where credits spent in a category go
+    pub fn set_spend_sink(env: Env, category_id: u32, sink: SpendSink) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().persistent().set(&DataKey::SpendSink(category_id), &sink);
+        env.events().publish((symbol_short!("sink"), category_id), sink);
+    }
+
+    /// Admin: allow a host to receive spends in a `SpendSink::Host` category
+    pub fn set_category_host(env: Env, category_id: u32, host: Address, allowed: bool) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        if allowed {
+            env.storage().persistent().set(&DataKey::CategoryHost(category_id, host.clone()), &true);
+        } else {
+            env.storage().persistent().remove(&DataKey::CategoryHost(category_id, host.clone()));
+        }
+        env.events().publish((symbol_short!("cat_host"), category_id, host), allowed);
+    }
+
+    pub fn set_limits(env: Env, max_supply: i128, daily_mint_limit: i128) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::MaxSupply, &max_supply);
+        env.storage().instance().set(&DataKey::DailyMintLimit, &daily_mint_limit);
+    }
+
+    pub fn rotate_admin(env: Env, new_admin: Address) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::Admin, &new_admin);
+        env.events().publish((symbol_short!("admin"),), new_admin);
+    }
+
+    pub fn set_paused(env: Env, paused: bool) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::Paused, &paused);
+    }
+
+    // === View Functions ===
+
+    pub fn get_balance(env: Env, user: Address) -> i128 {
+        env.storage().persistent()
+            .get::<_, UserCredits>(&DataKey::UserCredits(user))
+            .map(|c| c.balance)
+            .unwrap_or(0)
+    }
+
+    pub fn get_user_credits(env: Env, user: Address) -> UserCredits {
+        env.storage().persistent()
+            .get(&DataKey::UserCredits(user))
+            .unwrap_or(UserCredits {
+                balance: 0, lifetime_earned: 0, lifetime_spent: 0, last_activity: 0,
+            })
+    }
+
+    /// View: balance minus credits under active holds
+    pub fn get_spendable_balance(env: Env, user: Address) -> i128 {
+        Self::spendable_balance(&env, &user)
+    }
+
+    /// View: credits under active (unexpired) holds
+    pub fn get_held_balance(env: Env, user: Address) -> i128 {
+        Self::held_balance(&env, &user)
+    }
+
+    pub fn get_hold(env: Env, hold_id: BytesN<32>) -> CreditHold {
+        env.storage().persistent().get(&DataKey::Hold(hold_id)).expect("Hold not found")
+    }
+
+    pub fn get_package(env: Env, package_id: u32) -> CreditPackage {
+        env.storage().persistent().get(&DataKey::CreditPackage(package_id)).expect("Package not found")
+    }
+
+    pub fn get_all_packages(env: Env) -> Vec<CreditPackage> {
+        let counter: u32 = env.storage().instance().get(&DataKey::PackageCounter).unwrap_or(0);
+        let mut packages = Vec::new(&env);
+        for i in 1..=counter {
+            if let Some(pkg) = env.storage().persistent().get::<_, CreditPackage>(&DataKey::CreditPackage(i)) {
+                packages.push_back(pkg);
+            }
+        }
+        packages
+    }
+
+    pub fn get_promotion(env: Env, promotion_id: u32) -> Promotion {
+        env.storage().persistent().get(&PromotionKey::Promotion(promotion_id)).expect("Promotion not found")
+    }
+
+    /// View: promotions currently inside their window and not sold out
+    pub fn get_active_promotions(env: Env) -> Vec<Promotion> {
+        let counter: u32 = env.storage().instance().get(&PromotionKey::Counter).unwrap_or(0);
+        let mut promotions = Vec::new(&env);
+        for i in 1..=counter {
+            if let Some(promo) = env.storage().persistent().get::<_, Promotion>(&PromotionKey::Promotion(i)) {
+                if Self::promotion_live(&env, &promo) {
+                    promotions.push_back(promo);
+                }
+            }
+        }
+        promotions
+    }
+
+    pub fn get_promotion_purchases(env: Env, promotion_id: u32, user: Address) -> u32 {
+        env.storage().persistent().get(&PromotionKey::UserPurchases(promotion_id, user)).unwrap_or(0)
+    }
+
+    pub fn get_total_supply(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0)
+    }
+
+    pub fn get_max_supply(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::MaxSupply).unwrap_or(0)
+    }
+
+    /// View: credits minters may still issue today. Without a global budget this
+    /// is the remaining headroom under max supply.
+    pub fn get_remaining_emission_budget(env: Env) -> i128 {
+        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0);
+        let max_supply: i128 = env.storage().instance().get(&DataKey::MaxSupply).unwrap_or(0);
+        let supply_headroom = (max_supply - total_supply).max(0);
+
+        let budget: i128 = env.storage().instance().get(&DataKey::GlobalDailyBudget).unwrap_or(0);
+        if budget == 0 {
+            return supply_headroom;
+        }
+        let day = env.ledger().timestamp() / 86400;
+        let minted: i128 = env.storage().temporary().get(&DataKey::GlobalDailyMinted(day)).unwrap_or(0);
+        (budget - minted).max(0).min(supply_headroom)
+    }
+
+    /// View: credits a minter may still issue today (None if the minter has no quota)
+    pub fn get_minter_remaining_quota(env: Env, minter: Address) -> Option<i128> {
+        let quota: i128 = env.storage().persistent().get(&DataKey::MinterDailyQuota(minter.clone()))?;
+        let day = env.ledger().timestamp() / 86400;
+        let minted: i128 = env.storage().temporary()
+            .get(&DataKey::MinterDailyMinted(minter, day))
+            .unwrap_or(0);
+        Some((quota - minted).max(0))
+    }
+
+    pub fn get_spend_sink(env: Env, category_id: u32) -> SpendSink {
+        env.storage().persistent().get(&DataKey::SpendSink(category_id)).unwrap_or(SpendSink::Burn)
+    }
+
+    pub fn get_spend_category_stats(env: Env, category_id: u32) -> SpendCategoryStats {
+        env.storage().persistent()
+            .get(&DataKey::SpendCategoryStats(category_id))
+            .unwrap_or(SpendCategoryStats { total_spent: 0, total_burned: 0, total_routed: 0, spend_count: 0 })
+    }
+
+    pub fn is_category_host(env: Env, category_id: u32, host: Address) -> bool {
+        env.storage().persistent().has(&DataKey::CategoryHost(category_id, host))
+    }
+
+    /// View: credits routed to a recipient from a spend category
+    pub fn get_sink_earnings(env: Env, category_id: u32, recipient: Address) -> i128 {
+        env.storage().persistent().get(&DataKey::SinkEarnings(category_id, recipient)).unwrap_or(0)
+    }
+
+    pub fn has_role(env: Env, role: Role, account: Address) -> bool {
+        Self::role_active(&env, role, &account)
+    }
+
+    /// View: unexpired grants for a role
+    pub fn get_role_members(env: Env, role: Role) -> Vec<RoleGrant> {
+        Self::active_grants(&env, role)
+    }
+
+    pub fn get_minters(env: Env) -> Vec<Address> {
+        Self::active_accounts(&env, Role::Minter)
+    }
+
+    pub fn get_burners(env: Env) -> Vec<Address> {
+        Self::active_accounts(&env, Role::Burner)
+    }
+
+    /// View: role change log, oldest first, starting after `start_after`
+    pub fn get_role_history(env: Env, start_after: u64, limit: u32) -> Vec<RoleChange> {
+        let counter: u64 = env.storage().instance().get(&DataKey::RoleChangeCounter).unwrap_or(0);
+        let mut changes = Vec::new(&env);
+        let mut id = start_after + 1;
+        while id <= counter && changes.len() < limit {
+            if let Some(change) = env.storage().persistent().get::<_, RoleChange>(&DataKey::RoleChange(id)) {
+                changes.push_back(change);
+            }
+            id += 1;
+        }
+        changes
+    }
+
+    pub fn get_transfer_policy(env: Env) -> TransferPolicy {
+        env.storage().instance().get(&DataKey::TransferPolicy).unwrap_or(TransferPolicy::Open)
+    }
+
+    pub fn is_transfer_allowlisted(env: Env, contract: Address) -> bool {
+        env.storage().persistent().has(&DataKey::TransferAllowlist(contract))
+    }
+
+    pub fn is_paused(env: Env) -> bool {
+        env.storage().instance().get(&DataKey::Paused).unwrap_or(false)
+    }
+
+    // === Internal Functions ===
+
+    fn internal_set_activity_reward(env: &Env, activity_type_id: u32, reward_amount: i128, cooldown_seconds: u64, is_active: bool, daily_cap: u32) {
+        let activity_type = match activity_type_id {
+            0 => ActivityType::GamePlay,
+            1 => ActivityType::RadioListen,
+            2 => ActivityType::ChatMessage,
+            3 => ActivityType::GameWin,
+            4 => ActivityType::Achievement,
+            _ => panic!("Invalid activity type"),
+        };
+
+        let reward = ActivityReward {
+            activity_type,
+            reward_amount,
+            cooldown_seconds,
+            is_active,
+        };
+
+        env.storage().persistent().set(&DataKey::ActivityRewardConfig(activity_type_id), &reward);
+        env.storage().persistent().set(&DataKey::DailyActivityCap(activity_type_id), &daily_cap);
+    }
+
+    /// Shared eligibility checks and payout for activity rewards. Returns the
+    /// credited amount, or the reason the reward was not paid (no state changed).
+    fn internal_reward_activity(
+        env: &Env,
+        minter: &Address,
+        user: &Address,
+        activity_type_id: u32,
+        proof_verified: bool,
+    ) -> Result<i128, RewardStatus> {
+        // Get activity reward config
+        let reward: ActivityReward = match env.storage().persistent()
+            .get(&DataKey::ActivityRewardConfig(activity_type_id))
+        {
+            Some(r) => r,
+            None => return Err(RewardStatus::NotConfigured),
+        };
+
+        if !reward.is_active {
+            return Err(RewardStatus::Disabled);
+        }
+
+        if !proof_verified {
+            let proof: Option<ProofOfPlayConfig> = env.storage().persistent()
+                .get(&ProofKey::Config(activity_type_id));
+            if proof.map(|p| p.required).unwrap_or(false) {
+                return Err(RewardStatus::ProofRequired);
+            }
+        }
+
+        let now = env.ledger().timestamp();
+        let day = now / 86400;
+
+        // Check cooldown
+        if reward.cooldown_seconds > 0 {
+            let last_reward: u64 = env.storage().temporary()
+                .get(&DataKey::UserActivityCooldown(user.clone(), activity_type_id))
+                .unwrap_or(0);
+            if now - last_reward < reward.cooldown_seconds {
+                return Err(RewardStatus::OnCooldown);
+            }
+        }
+
+        // Check daily cap
+        let daily_cap: u32 = env.storage().persistent()
+            .get(&DataKey::DailyActivityCap(activity_type_id))
+            .unwrap_or(100);
+        let daily_count: u32 = env.storage().temporary()
+            .get(&DataKey::UserDailyActivityCount(user.clone(), day, activity_type_id))
+            .unwrap_or(0);
+        if daily_count >= daily_cap {
+            return Err(RewardStatus::DailyCapReached);
+        }
+
+        // Apply streak multiplier
+        let streak = Self::next_streak(env, user, activity_type_id, day);
+        let amount = Self::apply_streak_bonus(env, reward.reward_amount, streak.current_streak);
+
+        // Check supply cap
+        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
+        let max_supply: i128 = env.storage().instance().get(&DataKey::MaxSupply).unwrap();
+        if total_supply + amount > max_supply {
+            return Err(RewardStatus::MaxSupplyReached);
+        }
+
+        // Check daily mint limit
+        let daily_mint_key = DataKey::UserDailyMint(user.clone(), day);
+        let today_minted: i128 = env.storage().temporary().get(&daily_mint_key).unwrap_or(0);
+        let daily_limit: i128 = env.storage().instance().get(&DataKey::DailyMintLimit).unwrap();
+        if today_minted + amount > daily_limit {
+            return Err(RewardStatus::DailyMintLimitReached);
+        }
+
+        // Check global budget and minter quota
+        Self::check_emission(env, minter, day, amount)?;
+
+        // Update trackers
+        env.storage().temporary().set(
+            &DataKey::UserActivityCooldown(user.clone(), activity_type_id),
+            &now,
+        );
+        env.storage().temporary().set(
+            &DataKey::UserDailyActivityCount(user.clone(), day, activity_type_id),
+            &(daily_count + 1),
+        );
+        env.storage().temporary().set(&daily_mint_key, &(today_minted + amount));
+        env.storage().persistent().set(&DataKey::UserStreak(user.clone(), activity_type_id), &streak);
+        Self::record_emission(env, minter, day, amount);
+
+        // Determine tx type based on activity
+        let tx_type = match activity_type_id {
+            0 | 3 => CreditTxType::GameReward,
+            1 => CreditTxType::RadioReward,
+            2 => CreditTxType::ChatReward,
+            _ => CreditTxType::Earn,
+        };
+
+        let description = match activity_type_id {
+            0 => String::from_str(env, "Game play reward"),
+            1 => String::from_str(env, "Radio listening reward"),
+            2 => String::from_str(env, "Chat participation reward"),
+            3 => String::from_str(env, "Game win bonus"),
+            4 => String::from_str(env, "Achievement unlock reward"),
+            _ => String::from_str(env, "Activity reward"),
+        };
+
+        Self::internal_credit_user(env, user, amount, tx_type, description);
+        Self::internal_pay_referral(env, minter, user, amount, day);
+
+        env.events().publish((symbol_short!("activity"), user.clone(), activity_type_id), amount);
+
+        Ok(amount)
+    }
+
+
+    fn reward_status_message(status: &RewardStatus) -> &'static str {
+        match status {
+            RewardStatus::Rewarded => "Rewarded",
+            RewardStatus::NotConfigured => "Activity type not configured",
+            RewardStatus::Disabled => "Activity reward is disabled",
+            RewardStatus::OnCooldown => "Activity on cooldown",
+            RewardStatus::DailyCapReached => "Daily activity cap reached",
+            RewardStatus::MaxSupplyReached => "Would exceed max supply",
+            RewardStatus::DailyMintLimitReached => "Would exceed daily mint limit",
+            RewardStatus::EmissionBudgetReached => "Would exceed global emission budget",
+            RewardStatus::MinterQuotaReached => "Would exceed minter daily quota",
+            RewardStatus::ProofRequired => "Proof of play required",
+        }
+    }
+
+    fn check_emission(env: &Env, minter: &Address, day: u64, amount: i128) -> Result<(), RewardStatus> {
+        let budget: i128 = env.storage().instance().get(&DataKey::GlobalDailyBudget).unwrap_or(0);
+        if budget > 0 {
+            let minted: i128 = env.storage().temporary().get(&DataKey::GlobalDailyMinted(day)).unwrap_or(0);
+            if minted + amount > budget {
+                return Err(RewardStatus::EmissionBudgetReached);
+            }
+        }
+
+        let quota: Option<i128> = env.storage().persistent().get(&DataKey::MinterDailyQuota(minter.clone()));
+        if let Some(quota) = quota {
+            let minted: i128 = env.storage().temporary()
+                .get(&DataKey::MinterDailyMinted(minter.clone(), day))
+                .unwrap_or(0);
+            if minted + amount > quota {
+                return Err(RewardStatus::MinterQuotaReached);
+            }
+        }
+        Ok(())
+    }
+
+    /// Track minter-issued credits and emit an alert when today's global budget
+    /// or the minter's quota crosses the configured usage threshold.
+    fn record_emission(env: &Env, minter: &Address, day: u64, amount: i128) {
+        let alert_bps: u32 = env.storage().instance().get(&DataKey::EmissionAlertBps).unwrap_or(8000);
+
+        let global_key = DataKey::GlobalDailyMinted(day);
+        let global_before: i128 = env.storage().temporary().get(&global_key).unwrap_or(0);
+        let global_after = global_before + amount;
+        env.storage().temporary().set(&global_key, &global_after);
+
+        let budget: i128 = env.storage().instance().get(&DataKey::GlobalDailyBudget).unwrap_or(0);
+        if budget > 0 {
+            let threshold = budget * alert_bps as i128 / 10000;
+            if global_before < threshold && global_after >= threshold {
+                env.events().publish((symbol_short!("em_alert"), day), (global_after, budget));
+            }
+        }
+
+        let minter_key = DataKey::MinterDailyMinted(minter.clone(), day);
+        let minter_before: i128 = env.storage().temporary().get(&minter_key).unwrap_or(0);
+        let minter_after = minter_before + amount;
+        env.storage().temporary().set(&minter_key, &minter_after);
+
+        let quota: Option<i128> = env.storage().persistent().get(&DataKey::MinterDailyQuota(minter.clone()));
+        if let Some(quota) = quota {
+            let threshold = quota * alert_bps as i128 / 10000;
+            if minter_before < threshold && minter_after >= threshold {
+                env.events().publish((symbol_short!("mq_alert"), minter.clone(), day), (minter_after, quota));
+            }
+        }
+    }
+
+    fn next_streak(env: &Env, user: &Address, activity_type_id: u32, day: u64) -> StreakInfo {
+        let streak: Option<StreakInfo> = env.storage().persistent()
+            .get(&DataKey::UserStreak(user.clone(), activity_type_id));
+        let current_streak = match streak {
+            Some(s) if s.last_day == day => s.current_streak,
+            Some(s) if s.last_day + 1 == day => s.current_streak + 1,
+            _ => 1,
+        };
+        StreakInfo { current_streak, last_day: day }
+    }
+
+    fn apply_streak_bonus(env: &Env, base_amount: i128, current_streak: u32) -> i128 {
+        let config: StreakConfig = env.storage().instance()
+            .get(&DataKey::StreakConfig)
+            .unwrap_or(StreakConfig { bonus_bps_per_day: 0, max_bonus_bps: 0 });
+        let bonus_bps = (current_streak.saturating_sub(1))
+            .saturating_mul(config.bonus_bps_per_day)
+            .min(config.max_bonus_bps);
+        base_amount * (10000 + bonus_bps as i128) / 10000
+    }
+
+    /// Pay the referrer their share of a referee's reward. Skipped (rather than
+    /// failing the referee's reward) when the referral has lapsed or the payout
+    /// would exceed the referrer's daily mint limit, max supply or emission budget.
+    fn internal_pay_referral(env: &Env, minter: &Address, user: &Address, earned: i128, day: u64) {
+        let referral: Referral = match env.storage().persistent().get(&DataKey::Referral(user.clone())) {
+            Some(r) => r,
+            None => return,
+        };
+        let config: ReferralConfig = match env.storage().instance().get(&DataKey::ReferralConfig) {
+            Some(c) => c,
+            None => return,
+        };
+        if env.ledger().timestamp() > referral.registered_at + config.duration_days * 86400 {
+            return;
+        }
+
+        let amount = earned * config.reward_bps as i128 / 10000;
+        if amount <= 0 {
+            return;
+        }
+
+        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
+        let max_supply: i128 = env.storage().instance().get(&DataKey::MaxSupply).unwrap();
+        if total_supply + amount > max_supply {
+            return;
+        }
+
+        let daily_mint_key = DataKey::UserDailyMint(referral.referrer.clone(), day);
+        let today_minted: i128 = env.storage().temporary().get(&daily_mint_key).unwrap_or(0);
+        let daily_limit: i128 = env.storage().instance().get(&DataKey::DailyMintLimit).unwrap();
+        if today_minted + amount > daily_limit {
+            return;
+        }
+        if Self::check_emission(env, minter, day, amount).is_err() {
+            return;
+        }
+        env.storage().temporary().set(&daily_mint_key, &(today_minted + amount));
+        Self::record_emission(env, minter, day, amount);
+
+        let earnings_key = DataKey::ReferralEarnings(referral.referrer.clone());
+        let earnings: i128 = env.storage().persistent().get(&earnings_key).unwrap_or(0);
+        env.storage().persistent().set(&earnings_key, &(earnings + amount));
+
+        Self::internal_credit_user(env, &referral.referrer, amount, CreditTxType::ReferralReward,
+            String::from_str(env, "Referral reward"));
+
+        env.events().publish((symbol_short!("ref_pay"), referral.referrer, user.clone()), amount);
+    }
+
+    fn promotion_live(env: &Env, promo: &Promotion) -> bool {
+        let now = env.ledger().timestamp();
+        promo.is_active
+            && now >= promo.start_time
+            && now < promo.end_time
+            && (promo.global_cap == 0 || promo.sold_count < promo.global_cap)
+    }
+
+    fn find_promotion(env: &Env, package_id: u32, buyer: &Address) -> Option<Promotion> {
+        let promo_ids: Vec<u32> = env.storage().persistent()
+            .get(&PromotionKey::PackagePromotions(package_id))
+            .unwrap_or(Vec::new(env));
+        for promo_id in promo_ids.iter() {
+            let promo: Promotion = env.storage().persistent().get(&PromotionKey::Promotion(promo_id)).unwrap();
+            if !Self::promotion_live(env, &promo) {
+                continue;
+            }
+            if promo.per_user_limit > 0 {
+                let purchases: u32 = env.storage().persistent()
+                    .get(&PromotionKey::UserPurchases(promo_id, buyer.clone()))
+                    .unwrap_or(0);
+                if purchases >= promo.per_user_limit {
+                    continue;
+                }
+            }
+            return Some(promo);
+        }
+        None
+    }
+
+    fn internal_create_package(env: &Env, credits: i128, price_usdc: i128, bonus_credits: i128) -> u32 {
+        let counter: u32 = env.storage().instance().get(&DataKey::PackageCounter).unwrap_or(0);
+        let new_id = counter + 1;
+        let package = CreditPackage { id: new_id, credits, price_usdc, bonus_credits, is_active: true };
+        env.storage().persistent().set(&DataKey::CreditPackage(new_id), &package);
+        env.storage().instance().set(&DataKey::PackageCounter, &new_id);
+        new_id
+    }
+
+    fn internal_credit_user(env: &Env, user: &Address, amount: i128, tx_type: CreditTxType, description: String) {
+        Self::internal_receive(env, user, amount, tx_type, description);
+
+        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalSupply, &(total_supply + amount));
+    }
+
+    /// Add to a user's balance without minting (supply is unchanged)
+    fn internal_receive(env: &Env, user: &Address, amount: i128, tx_type: CreditTxType, description: String) {
+        let mut credits: UserCredits = env.storage().persistent()
+            .get(&DataKey::UserCredits(user.clone()))
+            .unwrap_or(UserCredits {
+                balance: 0, lifetime_earned: 0, lifetime_spent: 0, last_activity: 0,
+            });
+        
+        credits.balance += amount;
+        credits.lifetime_earned += amount;
+        credits.last_activity = env.ledger().timestamp();
+        
+        env.storage().persistent().set(&DataKey::UserCredits(user.clone()), &credits);
+        
+        Self::record_transaction(env, user.clone(), amount, tx_type, description);
+    }
+
+    /// Debit a spend and send it to the category's sink
+    fn internal_spend(
+        env: &Env,
+        user: &Address,
+        category_id: u32,
+        amount: i128,
+        beneficiary: Option<Address>,
+        description: String,
+    ) {
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let sink: SpendSink = env.storage().persistent()
+            .get(&DataKey::SpendSink(category_id))
+            .unwrap_or(SpendSink::Burn);
+        let destination = match sink {
+            SpendSink::Burn => None,
+            SpendSink::Treasury => Some(env.storage().instance().get::<_, Address>(&DataKey::Treasury).unwrap()),
+            SpendSink::Recipient(addr) => Some(addr),
+            SpendSink::Host => {
+                let host = beneficiary.expect("Beneficiary required");
+                if host == *user {
+                    panic!("Cannot spend to yourself");
+                }
+                if !env.storage().persistent().has(&DataKey::CategoryHost(category_id, host.clone())) {
+                    panic!("Beneficiary is not a host for this category");
+                }
+                Some(host)
+            }
+        };
+
+        Self::internal_debit_user(env, user, amount, CreditTxType::Spend, description);
+
+        let stats_key = DataKey::SpendCategoryStats(category_id);
+        let mut stats: SpendCategoryStats = env.storage().persistent()
+            .get(&stats_key)
+            .unwrap_or(SpendCategoryStats { total_spent: 0, total_burned: 0, total_routed: 0, spend_count: 0 });
+        stats.total_spent += amount;
+        stats.spend_count += 1;
+
+        match destination {
+            None => {
+                let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
+                env.storage().instance().set(&DataKey::TotalSupply, &(total_supply - amount));
+                stats.total_burned += amount;
+            }
+            Some(recipient) => {
+                Self::internal_receive(env, &recipient, amount, CreditTxType::Earn,
+                    String::from_str(env, "Spend revenue share"));
+                let earnings_key = DataKey::SinkEarnings(category_id, recipient.clone());
+                let earnings: i128 = env.storage().persistent().get(&earnings_key).unwrap_or(0);
+                env.storage().persistent().set(&earnings_key, &(earnings + amount));
+                stats.total_routed += amount;
+                env.events().publish((symbol_short!("sink_pay"), recipient, category_id), amount);
+            }
+        }
+
+        env.storage().persistent().set(&stats_key, &stats);
+    }
+
+    fn internal_transfer(env: &Env, from: &Address, to: &Address, amount: i128) {
+        Self::require_transfer_allowed(env, from, to);
+
+        Self::internal_debit_user(env, from, amount, CreditTxType::Transfer,
+            String::from_str(env, "Transfer out"));
+        Self::internal_receive(env, to, amount, CreditTxType::Transfer,
+            String::from_str(env, "Transfer in"));
+    }
+
+    fn internal_burn(env: &Env, from: &Address, amount: i128) {
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        Self::internal_debit_user(env, from, amount, CreditTxType::Burn,
+            String::from_str(env, "Credits burned"));
+
+        let total_supply: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap();
+        env.storage().instance().set(&DataKey::TotalSupply, &(total_supply - amount));
+    }
+
+    fn read_allowance(env: &Env, from: &Address, spender: &Address) -> AllowanceValue {
+        let allowance: Option<AllowanceValue> = env.storage().temporary()
+            .get(&DataKey::Allowance(from.clone(), spender.clone()));
+        match allowance {
+            Some(a) if a.expiration_ledger >= env.ledger().sequence() => a,
+            _ => AllowanceValue { amount: 0, expiration_ledger: 0 },
+        }
+    }
+
+    fn spend_allowance(env: &Env, from: &Address, spender: &Address, amount: i128) {
+        let allowance = Self::read_allowance(env, from, spender);
+        if allowance.amount < amount {
+            panic!("Insufficient allowance");
+        }
+        env.storage().temporary().set(
+            &DataKey::Allowance(from.clone(), spender.clone()),
+            &AllowanceValue { amount: allowance.amount - amount, expiration_ledger: allowance.expiration_ledger },
+        );
+    }
+
+    fn require_transfer_allowed(env: &Env, from: &Address, to: &Address) {
+        let policy: TransferPolicy = env.storage().instance()
+            .get(&DataKey::TransferPolicy)
+            .unwrap_or(TransferPolicy::Open);
+        match policy {
+            TransferPolicy::Open => {}
+            TransferPolicy::Soulbound => panic!("Credits are non-transferable"),
+            TransferPolicy::Allowlisted => {
+                let allowed = env.storage().persistent().has(&DataKey::TransferAllowlist(to.clone()))
+                    || env.storage().persistent().has(&DataKey::TransferAllowlist(from.clone()));
+                if !allowed {
+                    panic!("Transfer destination not allowlisted");
+                }
+            }
+        }
+    }
+
+    fn internal_debit_user(env: &Env, user: &Address, amount: i128, tx_type: CreditTxType, description: String) {
+        let mut credits: UserCredits = env.storage().persistent()
+            .get(&DataKey::UserCredits(user.clone()))
+            .expect("User has no credits");
+        
+        if credits.balance - Self::held_balance(env, user) < amount {
+            panic!("Insufficient credits");
+        }
+        
+        credits.balance -= amount;
+        credits.lifetime_spent += amount;
+        credits.last_activity = env.ledger().timestamp();
+        
+        env.storage().persistent().set(&DataKey::UserCredits(user.clone()), &credits);
+        
+        Self::record_transaction(env, user.clone(), -amount, tx_type, description);
+    }
+
+    fn held_balance(env: &Env, user: &Address) -> i128 {
+        let hold_ids: Vec<BytesN<32>> = env.storage().persistent()
+            .get(&DataKey::UserHolds(user.clone()))
+            .unwrap_or(Vec::new(env));
+        let now = env.ledger().timestamp();
+        let mut held = 0i128;
+        for hold_id in hold_ids.iter() {
+            if let Some(hold) = env.storage().persistent().get::<_, CreditHold>(&DataKey::Hold(hold_id)) {
+                if hold.status == HoldStatus::Active && now < hold.expiry {
+                    held += hold.amount;
+                }
+            }
+        }
+        held
+    }
+
+    fn spendable_balance(env: &Env, user: &Address) -> i128 {
+        let balance: i128 = env.storage().persistent()
+            .get::<_, UserCredits>(&DataKey::UserCredits(user.clone()))
+            .map(|c| c.balance)
+            .unwrap_or(0);
+        balance - Self::held_balance(env, user)
+    }
+
+    /// Drop captured, released and expired holds from a user's open hold list
+    fn prune_holds(env: &Env, user: &Address) -> Vec<BytesN<32>> {
+        let hold_ids: Vec<BytesN<32>> = env.storage().persistent()
+            .get(&DataKey::UserHolds(user.clone()))
+            .unwrap_or(Vec::new(env));
+        let now = env.ledger().timestamp();
+        let mut open_holds = Vec::new(env);
+        for hold_id in hold_ids.iter() {
+            if let Some(hold) = env.storage().persistent().get::<_, CreditHold>(&DataKey::Hold(hold_id.clone())) {
+                if hold.status == HoldStatus::Active && now < hold.expiry {
+                    open_holds.push_back(hold_id);
+                }
+            }
+        }
+        open_holds
+    }
+
+    fn record_transaction(env: &Env, user: Address, amount: i128, tx_type: CreditTxType, description: String) {
+        let counter: u64 = env.storage().instance().get(&DataKey::TxCounter).unwrap_or(0);
+        let new_id = counter + 1;
+        let tx = CreditTransaction { id: new_id, user, amount, tx_type, description, timestamp: env.ledger().timestamp() };
+        env.storage().persistent().set(&DataKey::Transaction(new_id), &tx);
+        env.storage().instance().set(&DataKey::TxCounter, &new_id);
+    }
+
+    fn require_not_paused(env: &Env) {
+        if env.storage().instance().get(&DataKey::Paused).unwrap_or(false) {
+            panic!("Contract is paused");
+        }
+    }
+
+    fn require_minter(env: &Env, addr: &Address) {
+        if !Self::role_active(env, Role::Minter, addr) { panic!("Not a minter"); }
+    }
+
+    fn require_hold_operator(env: &Env, addr: &Address) {
+        if !Self::role_active(env, Role::HoldOperator, addr) { panic!("Not a hold operator"); }
+    }
+
+    /// The hold's own operator, while it still has the role, or the admin
+    fn require_hold_owner(env: &Env, addr: &Address, hold: &CreditHold) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        if *addr == admin {
+            return;
+        }
+        Self::require_hold_operator(env, addr);
+        if *addr != hold.operator { panic!("Not the hold's operator"); }
+    }
+
+    fn require_burner(env: &Env, addr: &Address) {
+        if !Self::role_active(env, Role::Burner, addr) { panic!("Not a burner"); }
+    }
+
+    fn role_active(env: &Env, role: Role, addr: &Address) -> bool {
+        let grants: Vec<RoleGrant> = env.storage().instance()
+            .get(&DataKey::RoleMembers(role))
+            .unwrap_or(Vec::new(env));
+        let now = env.ledger().timestamp();
+        grants.iter().any(|g| &g.account == addr && (g.expires_at == 0 || now < g.expires_at))
+    }
+
+    fn active_grants(env: &Env, role: Role) -> Vec<RoleGrant> {
+        let grants: Vec<RoleGrant> = env.storage().instance()
+            .get(&DataKey::RoleMembers(role))
+            .unwrap_or(Vec::new(env));
+        let now = env.ledger().timestamp();
+        let mut active = Vec::new(env);
+        for grant in grants.iter() {
+            if grant.expires_at == 0 || now < grant.expires_at {
+                active.push_back(grant);
+            }
+        }
+        active
+    }
+
+    fn active_accounts(env: &Env, role: Role) -> Vec<Address> {
+        let mut accounts = Vec::new(env);
+        for grant in Self::active_grants(env, role).iter() {
+            accounts.push_back(grant.account);
+        }
+        accounts
+    }
+
+    fn internal_grant_role(env: &Env, role: Role, account: &Address, expires_at: u64) {
+        if expires_at != 0 && expires_at <= env.ledger().timestamp() {
+            panic!("Expiry must be in the future");
+        }
+
+        // Expired grants are dropped whenever the member list is rewritten
+        let mut grants = Self::active_grants(env, role.clone());
+        match grants.iter().position(|g| &g.account == account) {
+            Some(i) => {
+                if grants.get(i as u32).unwrap().expires_at == expires_at {
+                    return;
+                }
+                grants.set(i as u32, RoleGrant { account: account.clone(), expires_at });
+            }
+            None => grants.push_back(RoleGrant { account: account.clone(), expires_at }),
+        }
+        env.storage().instance().set(&DataKey::RoleMembers(role.clone()), &grants);
+
+        Self::record_role_change(env, role, account, RoleAction::Granted, expires_at);
+    }
+
+    fn internal_revoke_role(env: &Env, role: Role, account: &Address) {
+        let grants: Vec<RoleGrant> = env.storage().instance()
+            .get(&DataKey::RoleMembers(role.clone()))
+            .unwrap_or(Vec::new(env));
+        let mut remaining = Vec::new(env);
+        let mut found = false;
+        for grant in grants.iter() {
+            if &grant.account == account {
+                found = true;
+            } else {
+                remaining.push_back(grant);
+            }
+        }
+        if !found {
+            return;
+        }
+        env.storage().instance().set(&DataKey::RoleMembers(role.clone()), &remaining);
+
+        Self::record_role_change(env, role, account, RoleAction::Revoked, 0);
+    }
+
+    fn record_role_change(env: &Env, role: Role, account: &Address, action: RoleAction, expires_at: u64) {
+        let counter: u64 = env.storage().instance().get(&DataKey::RoleChangeCounter).unwrap_or(0);
+        let new_id = counter + 1;
+        let change = RoleChange {
+            id: new_id,
+            role: role.clone(),
+            account: account.clone(),
+            action: action.clone(),
+            expires_at,
+            timestamp: env.ledger().timestamp(),
+        };
+        env.storage().persistent().set(&DataKey::RoleChange(new_id), &change);
+        env.storage().instance().set(&DataKey::RoleChangeCounter, &new_id);
+
+        env.events().publish((symbol_short!("role"), role, account.clone()), action);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use soroban_sdk::testutils::{Address as _, Events, Ledger};
+    use soroban_sdk::{Symbol, TryFromVal};
+
+    const UNIT: i128 = 10_000_000; // 7 decimals
+    const START: u64 = 1_700_000_000;
+
+    fn setup<'a>() -> (Env, ComputeCreditsContractClient<'a>, Address) {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger().with_mut(|l| l.timestamp = START);
+
+        let contract_id = env.register_contract(None, ComputeCreditsContract);
+        let client = ComputeCreditsContractClient::new(&env, &contract_id);
+        let admin = Address::generate(&env);
+        let usdc = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        client.initialize(&admin, &usdc, &(1_000_000_000 * UNIT), &(10_000 * UNIT));
+        (env, client, admin)
+    }
+
+    fn sign_receipt(
+        env: &Env,
+        client: &ComputeCreditsContractClient,
+        key: &SigningKey,
+        user: &Address,
+        package_id: u32,
+        receipt_id: &BytesN<32>,
+    ) -> BytesN<64> {
+        let message = (client.address.clone(), user.clone(), package_id, receipt_id.clone()).to_xdr(env);
+        let message: std::vec::Vec<u8> = message.iter().collect();
+        BytesN::from_array(env, &key.sign(&message).to_bytes())
+    }
+
+    #[test]
+    fn test_redeem_purchase_receipt() {
+        let (env, client, _admin) = setup();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        client.set_payment_oracle(&BytesN::from_array(&env, &key.verifying_key().to_bytes()));
+
+        let user = Address::generate(&env);
+        let receipt_id = BytesN::from_array(&env, &[1; 32]);
+        let signature = sign_receipt(&env, &client, &key, &user, 2, &receipt_id);
+
+        // Package 2: 500 CCC + 50 bonus
+        assert_eq!(client.redeem_purchase_receipt(&user, &2, &receipt_id, &signature), 550 * UNIT);
+        assert_eq!(client.get_balance(&user), 550 * UNIT);
+        assert!(client.is_receipt_redeemed(&receipt_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "Receipt already redeemed")]
+    fn test_receipt_replay_rejected() {
+        let (env, client, _admin) = setup();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        client.set_payment_oracle(&BytesN::from_array(&env, &key.verifying_key().to_bytes()));
+
+        let user = Address::generate(&env);
+        let receipt_id = BytesN::from_array(&env, &[1; 32]);
+        let signature = sign_receipt(&env, &client, &key, &user, 1, &receipt_id);
+
+        client.redeem_purchase_receipt(&user, &1, &receipt_id, &signature);
+        client.redeem_purchase_receipt(&user, &1, &receipt_id, &signature);
+    }
+
+    #[test]
+    fn test_receipt_bad_signature_rejected() {
+        let (env, client, _admin) = setup();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let forger = SigningKey::from_bytes(&[8; 32]);
+        client.set_payment_oracle(&BytesN::from_array(&env, &key.verifying_key().to_bytes()));
+
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let receipt_id = BytesN::from_array(&env, &[1; 32]);
+
+        // Signed by the wrong key
+        let forged = sign_receipt(&env, &client, &forger, &user, 1, &receipt_id);
+        assert!(client.try_redeem_purchase_receipt(&user, &1, &receipt_id, &forged).is_err());
+
+        // Valid signature, but for another user and package
+        let signature = sign_receipt(&env, &client, &key, &user, 1, &receipt_id);
+        assert!(client.try_redeem_purchase_receipt(&other, &1, &receipt_id, &signature).is_err());
+        assert!(client.try_redeem_purchase_receipt(&user, &3, &receipt_id, &signature).is_err());
+
+        assert!(!client.is_receipt_redeemed(&receipt_id));
+        assert_eq!(client.get_balance(&user), 0);
+    }
+
+    #[test]
+    fn test_streak_bonus() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+
+        // GamePlay pays 2 CCC, +10% per consecutive day
+        assert_eq!(client.reward_activity(&admin, &user, &0), 2 * UNIT);
+        assert_eq!(client.get_streak(&user, &0), 1);
+
+        env.ledger().with_mut(|l| l.timestamp += 86_400);
+        assert_eq!(client.reward_activity(&admin, &user, &0), 2 * UNIT * 11 / 10);
+        env.ledger().with_mut(|l| l.timestamp += 86_400);
+        assert_eq!(client.reward_activity(&admin, &user, &0), 2 * UNIT * 12 / 10);
+        assert_eq!(client.get_streak(&user, &0), 3);
+
+        // Same-day rewards keep the streak without extending it
+        env.ledger().with_mut(|l| l.timestamp += 60);
+        assert_eq!(client.reward_activity(&admin, &user, &0), 2 * UNIT * 12 / 10);
+
+        // Missing a day resets it
+        env.ledger().with_mut(|l| l.timestamp += 2 * 86_400);
+        assert_eq!(client.get_streak(&user, &0), 0);
+        assert_eq!(client.reward_activity(&admin, &user, &0), 2 * UNIT);
+        assert_eq!(client.get_streak(&user, &0), 1);
+    }
+
+    #[test]
+    fn test_referral_bonus_expires() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        let referrer = Address::generate(&env);
+        client.register_referrer(&user, &referrer);
+
+        // Referrer earns 5% of the referee's rewards
+        client.reward_activity(&admin, &user, &4);
+        assert_eq!(client.get_referral_earnings(&referrer), UNIT / 2);
+        assert_eq!(client.get_balance(&referrer), UNIT / 2);
+
+        // ...for 30 days after registration
+        env.ledger().with_mut(|l| l.timestamp += 31 * 86_400);
+        client.reward_activity(&admin, &user, &4);
+        assert_eq!(client.get_referral_earnings(&referrer), UNIT / 2);
+        assert_eq!(client.get_balance(&user), 20 * UNIT);
+    }
+
+    #[test]
+    #[should_panic(expected = "Referrer already registered")]
+    fn test_referrer_registered_once() {
+        let (env, client, _admin) = setup();
+        let user = Address::generate(&env);
+        client.register_referrer(&user, &Address::generate(&env));
+        client.register_referrer(&user, &Address::generate(&env));
+    }
+
+    #[test]
+    fn test_batch_rewards_report_skips() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.set_activity_reward(&1, &UNIT, &300, &false, &10);
+        client.set_activity_reward(&3, &(5 * UNIT), &0, &true, &1);
+
+        let mut entries = Vec::new(&env);
+        entries.push_back((user.clone(), 0));
+        entries.push_back((user.clone(), 0));
+        entries.push_back((user.clone(), 1));
+        entries.push_back((user.clone(), 3));
+        entries.push_back((user.clone(), 3));
+        entries.push_back((user.clone(), 9));
+
+        let results = client.reward_activities_batch(&admin, &entries);
+        let statuses: std::vec::Vec<RewardStatus> = results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, [
+            RewardStatus::Rewarded,
+            RewardStatus::OnCooldown,
+            RewardStatus::Disabled,
+            RewardStatus::Rewarded,
+            RewardStatus::DailyCapReached,
+            RewardStatus::NotConfigured,
+        ]);
+        assert_eq!(results.get(0).unwrap().amount, 2 * UNIT);
+        assert_eq!(results.get(1).unwrap().amount, 0);
+        assert_eq!(results.get(3).unwrap().amount, 5 * UNIT);
+        assert_eq!(client.get_balance(&user), 7 * UNIT);
+    }
+
+    // Number of events published so far with `name` as their first topic
+    fn emitted(env: &Env, name: Symbol) -> usize {
+        env.events().all().iter().filter(|(_, topics, _)| {
+            topics.get(0).and_then(|t| Symbol::try_from_val(env, &t).ok()) == Some(name.clone())
+        }).count()
+    }
+
+    #[test]
+    fn test_emission_budget_alert() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.set_emission_budget(&(10 * UNIT), &8000);
+
+        client.award_credits(&admin, &user, &(7 * UNIT), &String::from_str(&env, "award"));
+        assert_eq!(emitted(&env, symbol_short!("em_alert")), 0);
+        assert_eq!(client.get_remaining_emission_budget(), 3 * UNIT);
+
+        // Crossing 80% of the budget alerts once
+        client.award_credits(&admin, &user, &UNIT, &String::from_str(&env, "award"));
+        assert_eq!(emitted(&env, symbol_short!("em_alert")), 1);
+        client.award_credits(&admin, &user, &UNIT, &String::from_str(&env, "award"));
+        assert_eq!(emitted(&env, symbol_short!("em_alert")), 1);
+
+        // Over-budget rewards are skipped; the budget resets the next day
+        let mut entries = Vec::new(&env);
+        entries.push_back((user.clone(), 0));
+        let result = client.reward_activities_batch(&admin, &entries).get(0).unwrap();
+        assert_eq!(result.status, RewardStatus::EmissionBudgetReached);
+
+        env.ledger().with_mut(|l| l.timestamp += 86_400);
+        assert_eq!(client.get_remaining_emission_budget(), 10 * UNIT);
+    }
+
+    #[test]
+    fn test_minter_quota_alert() {
+        let (env, client, admin) = setup();
+        let minter = Address::generate(&env);
+        let user = Address::generate(&env);
+        client.add_minter(&minter);
+        client.set_minter_quota(&minter, &(5 * UNIT));
+
+        client.award_credits(&minter, &user, &(3 * UNIT), &String::from_str(&env, "award"));
+        assert_eq!(emitted(&env, symbol_short!("mq_alert")), 0);
+        client.award_credits(&minter, &user, &UNIT, &String::from_str(&env, "award"));
+        assert_eq!(emitted(&env, symbol_short!("mq_alert")), 1);
+        assert_eq!(client.get_minter_remaining_quota(&minter), Some(UNIT));
+
+        // The quota binds only that minter
+        let result = client.try_award_credits(&minter, &user, &(2 * UNIT), &String::from_str(&env, "award"));
+        assert!(result.is_err());
+        client.award_credits(&admin, &user, &(2 * UNIT), &String::from_str(&env, "award"));
+        assert_eq!(client.get_minter_remaining_quota(&admin), None);
+        assert_eq!(client.get_balance(&user), 6 * UNIT);
+    }
+
+    #[test]
+    fn test_hold_excluded_from_spendable_balance() {
+        let (env, client, admin) = setup();
+        let operator = Address::generate(&env);
+        let user = Address::generate(&env);
+        client.add_hold_operator(&operator);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        let hold_id = BytesN::from_array(&env, &[1; 32]);
+        client.hold_credits(&operator, &user, &(60 * UNIT), &hold_id, &(START + 3600));
+        assert_eq!(client.get_held_balance(&user), 60 * UNIT);
+        assert_eq!(client.get_spendable_balance(&user), 40 * UNIT);
+
+        let spend = client.try_spend_credits(&user, &(50 * UNIT), &String::from_str(&env, "spend"));
+        assert!(spend.is_err());
+        let second = BytesN::from_array(&env, &[2; 32]);
+        assert!(client.try_hold_credits(&operator, &user, &(50 * UNIT), &second, &(START + 3600)).is_err());
+
+        // Capturing spends exactly the held credits
+        client.capture_hold(&operator, &hold_id);
+        assert_eq!(client.get_hold(&hold_id).status, HoldStatus::Captured);
+        assert_eq!(client.get_balance(&user), 40 * UNIT);
+        assert_eq!(client.get_spendable_balance(&user), 40 * UNIT);
+    }
+
+    #[test]
+    fn test_hold_expiry() {
+        let (env, client, admin) = setup();
+        let operator = Address::generate(&env);
+        let user = Address::generate(&env);
+        let keeper = Address::generate(&env);
+        client.add_hold_operator(&operator);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        let hold_id = BytesN::from_array(&env, &[1; 32]);
+        client.hold_credits(&operator, &user, &(60 * UNIT), &hold_id, &(START + 3600));
+        assert!(client.try_release_hold(&keeper, &hold_id).is_err());
+
+        // A lapsed hold no longer locks credits and cannot be captured
+        env.ledger().with_mut(|l| l.timestamp = START + 3600);
+        assert_eq!(client.get_spendable_balance(&user), 100 * UNIT);
+        assert!(client.try_capture_hold(&operator, &hold_id).is_err());
+
+        // Anyone may tidy it up
+        client.release_hold(&keeper, &hold_id);
+        assert_eq!(client.get_hold(&hold_id).status, HoldStatus::Released);
+        assert_eq!(client.get_balance(&user), 100 * UNIT);
+    }
+
+    #[test]
+    fn test_hold_bound_to_its_operator() {
+        let (env, client, admin) = setup();
+        let operator = Address::generate(&env);
+        let rival = Address::generate(&env);
+        let user = Address::generate(&env);
+        client.add_hold_operator(&operator);
+        client.add_hold_operator(&rival);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        let hold_id = BytesN::from_array(&env, &[1; 32]);
+        client.hold_credits(&operator, &user, &(60 * UNIT), &hold_id, &(START + 3600));
+
+        assert!(client.try_capture_hold(&rival, &hold_id).is_err());
+        assert!(client.try_release_hold(&rival, &hold_id).is_err());
+
+        // The admin can always step in
+        client.release_hold(&admin, &hold_id);
+        assert_eq!(client.get_hold(&hold_id).status, HoldStatus::Released);
+        assert_eq!(client.get_spendable_balance(&user), 100 * UNIT);
+    }
+
+    #[test]
+    fn test_balance_excludes_holds() {
+        let (env, client, admin) = setup();
+        let operator = Address::generate(&env);
+        let user = Address::generate(&env);
+        client.add_hold_operator(&operator);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        let hold_id = BytesN::from_array(&env, &[1; 32]);
+        client.hold_credits(&operator, &user, &(30 * UNIT), &hold_id, &(START + 3600));
+        assert_eq!(client.balance(&user), 70 * UNIT);
+        assert_eq!(client.get_balance(&user), 100 * UNIT);
+    }
+
+    #[test]
+    fn test_transfer_open_policy() {
+        let (env, client, admin) = setup();
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        client.award_credits(&admin, &alice, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        client.transfer(&alice, &bob, &(40 * UNIT));
+        assert_eq!(client.balance(&alice), 60 * UNIT);
+        assert_eq!(client.balance(&bob), 40 * UNIT);
+    }
+
+    #[test]
+    #[should_panic(expected = "Credits are non-transferable")]
+    fn test_transfer_soulbound_policy() {
+        let (env, client, admin) = setup();
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        client.award_credits(&admin, &alice, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        client.set_transfer_policy(&TransferPolicy::Soulbound);
+        client.transfer(&alice, &bob, &(40 * UNIT));
+    }
+
+    #[test]
+    fn test_transfer_allowlisted_policy() {
+        let (env, client, admin) = setup();
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let raffle = Address::generate(&env);
+        client.award_credits(&admin, &alice, &(100 * UNIT), &String::from_str(&env, "award"));
+        client.set_transfer_policy(&TransferPolicy::Allowlisted);
+        client.set_transfer_allowlisted(&raffle, &true);
+
+        // Peer-to-peer is blocked, allowlisted contracts can send and receive
+        assert!(client.try_transfer(&alice, &bob, &(10 * UNIT)).is_err());
+        client.transfer(&alice, &raffle, &(40 * UNIT));
+        client.transfer(&raffle, &bob, &(15 * UNIT));
+        assert_eq!(client.balance(&raffle), 25 * UNIT);
+        assert_eq!(client.balance(&bob), 15 * UNIT);
+
+        client.set_transfer_allowlisted(&raffle, &false);
+        let blocked = client.try_transfer(&alice, &raffle, &(10 * UNIT));
+        assert!(blocked.is_err());
+    }
+
+    #[test]
+    fn test_transfer_from_allowance() {
+        let (env, client, admin) = setup();
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let spender = Address::generate(&env);
+        client.award_credits(&admin, &alice, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        let expiration = env.ledger().sequence() + 100;
+        client.approve(&alice, &spender, &(50 * UNIT), &expiration);
+        assert_eq!(client.allowance(&alice, &spender), 50 * UNIT);
+
+        client.transfer_from(&spender, &alice, &bob, &(30 * UNIT));
+        assert_eq!(client.allowance(&alice, &spender), 20 * UNIT);
+        assert_eq!(client.balance(&bob), 30 * UNIT);
+        assert!(client.try_transfer_from(&spender, &alice, &bob, &(21 * UNIT)).is_err());
+
+        client.burn_from(&spender, &alice, &(20 * UNIT));
+        assert_eq!(client.allowance(&alice, &spender), 0);
+        assert_eq!(client.balance(&alice), 50 * UNIT);
+    }
+
+    #[test]
+    fn test_allowance_expires() {
+        let (env, client, admin) = setup();
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let spender = Address::generate(&env);
+        client.award_credits(&admin, &alice, &(100 * UNIT), &String::from_str(&env, "award"));
+
+        let expiration = env.ledger().sequence() + 10;
+        client.approve(&alice, &spender, &(50 * UNIT), &expiration);
+        env.ledger().with_mut(|l| l.sequence_number = expiration + 1);
+
+        assert_eq!(client.allowance(&alice, &spender), 0);
+        assert!(client.try_transfer_from(&spender, &alice, &bob, &UNIT).is_err());
+    }
+
+    #[test]
+    fn test_spend_sink_burn_vs_route() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        let creator = Address::generate(&env);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+        client.set_spend_sink(&1, &SpendSink::Recipient(creator.clone()));
+        let supply = client.get_total_supply();
+
+        client.spend_in_category(&user, &0, &(10 * UNIT), &None, &String::from_str(&env, "burn"));
+        let burned = client.get_spend_category_stats(&0);
+        assert_eq!(burned.total_burned, 10 * UNIT);
+        assert_eq!(burned.total_routed, 0);
+        assert_eq!(client.get_total_supply(), supply - 10 * UNIT);
+
+        client.spend_in_category(&user, &1, &(25 * UNIT), &None, &String::from_str(&env, "route"));
+        let routed = client.get_spend_category_stats(&1);
+        assert_eq!(routed.total_spent, 25 * UNIT);
+        assert_eq!(routed.total_burned, 0);
+        assert_eq!(routed.total_routed, 25 * UNIT);
+        assert_eq!(routed.spend_count, 1);
+        assert_eq!(client.get_total_supply(), supply - 10 * UNIT);
+        assert_eq!(client.get_balance(&creator), 25 * UNIT);
+        assert_eq!(client.get_sink_earnings(&1, &creator), 25 * UNIT);
+        assert_eq!(client.get_balance(&user), 65 * UNIT);
+    }
+
+    #[test]
+    fn test_spend_sink_treasury() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        let treasury = Address::generate(&env);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+        client.set_treasury(&treasury);
+        client.set_spend_sink(&2, &SpendSink::Treasury);
+
+        client.spend_in_category(&user, &2, &(30 * UNIT), &None, &String::from_str(&env, "fee"));
+        assert_eq!(client.get_balance(&treasury), 30 * UNIT);
+        assert_eq!(client.get_sink_earnings(&2, &treasury), 30 * UNIT);
+    }
+
+    #[test]
+    fn test_spend_sink_host_requires_registered_host() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        let host = Address::generate(&env);
+        let stranger = Address::generate(&env);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+        client.set_spend_sink(&3, &SpendSink::Host);
+        client.set_category_host(&3, &host, &true);
+        let desc = String::from_str(&env, "entry");
+
+        assert!(client.try_spend_in_category(&user, &3, &(10 * UNIT), &None, &desc).is_err());
+        assert!(client.try_spend_in_category(&user, &3, &(10 * UNIT), &Some(stranger.clone()), &desc).is_err());
+
+        client.spend_in_category(&user, &3, &(10 * UNIT), &Some(host.clone()), &desc);
+        assert_eq!(client.get_balance(&host), 10 * UNIT);
+        assert_eq!(client.get_sink_earnings(&3, &host), 10 * UNIT);
+
+        client.set_category_host(&3, &host, &false);
+        assert!(!client.is_category_host(&3, &host));
+        assert!(client.try_spend_in_category(&user, &3, &(10 * UNIT), &Some(host.clone()), &desc).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot spend to yourself")]
+    fn test_spend_sink_host_rejects_self() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.award_credits(&admin, &user, &(100 * UNIT), &String::from_str(&env, "award"));
+        client.set_spend_sink(&3, &SpendSink::Host);
+        client.set_category_host(&3, &user, &true);
+
+        client.spend_in_category(&user, &3, &(10 * UNIT), &Some(user.clone()), &String::from_str(&env, "entry"));
+    }
+
+    #[test]
+    fn test_role_expiry() {
+        let (env, client, _admin) = setup();
+        let minter = Address::generate(&env);
+        let user = Address::generate(&env);
+        client.grant_role(&Role::Minter, &minter, &(START + 3600));
+        assert!(client.has_role(&Role::Minter, &minter));
+        assert!(client.get_minters().contains(&minter));
+        client.award_credits(&minter, &user, &UNIT, &String::from_str(&env, "award"));
+
+        env.ledger().with_mut(|l| l.timestamp = START + 3600);
+        assert!(!client.has_role(&Role::Minter, &minter));
+        assert!(!client.get_minters().contains(&minter));
+        let expired = client.try_award_credits(&minter, &user, &UNIT, &String::from_str(&env, "award"));
+        assert!(expired.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Expiry must be in the future")]
+    fn test_role_expiry_in_past_rejected() {
+        let (env, client, _admin) = setup();
+        client.grant_role(&Role::Burner, &Address::generate(&env), &START);
+    }
+
+    #[test]
+    fn test_role_grants_idempotent() {
+        let (env, client, _admin) = setup();
+        let operator = Address::generate(&env);
+        let logged = client.get_role_history(&0, &100).len();
+
+        client.grant_role(&Role::HoldOperator, &operator, &0);
+        client.grant_role(&Role::HoldOperator, &operator, &0);
+        assert_eq!(client.get_role_members(&Role::HoldOperator).len(), 1);
+        assert_eq!(client.get_role_history(&0, &100).len(), logged + 1);
+
+        // Changing the expiry updates the grant in place and is logged
+        client.grant_role(&Role::HoldOperator, &operator, &(START + 60));
+        let members = client.get_role_members(&Role::HoldOperator);
+        assert_eq!(members.len(), 1);
+        assert_eq!(members.get(0).unwrap().expires_at, START + 60);
+
+        client.revoke_role(&Role::HoldOperator, &operator);
+        client.revoke_role(&Role::HoldOperator, &operator);
+        assert!(!client.has_role(&Role::HoldOperator, &operator));
+
+        let history = client.get_role_history(&(logged as u64), &100);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(0).unwrap().action, RoleAction::Granted);
+        assert_eq!(history.get(1).unwrap().expires_at, START + 60);
+        assert_eq!(history.get(2).unwrap().action, RoleAction::Revoked);
+    }
+
+    // sha256(session_id || score BE || duration BE), as committed by the client
+    fn play_commitment(env: &Env, session_id: &BytesN<32>, score: u64, duration: u64) -> BytesN<32> {
+        let mut preimage = Bytes::from_array(env, &session_id.to_array());
+        preimage.extend_from_array(&score.to_be_bytes());
+        preimage.extend_from_array(&duration.to_be_bytes());
+        env.crypto().sha256(&preimage).into()
+    }
+
+    #[test]
+    fn test_proof_of_play_reward() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.set_proof_of_play(&3, &true, &120);
+        let session_id = BytesN::from_array(&env, &[7; 32]);
+
+        // Plain rewards are refused once proof is required
+        let plain = client.try_reward_activity(&admin, &user, &3);
+        assert!(plain.is_err());
+        let batch = client.reward_activities_batch(&admin, &Vec::from_array(&env, [(user.clone(), 3)]));
+        assert_eq!(batch.get(0).unwrap().status, RewardStatus::ProofRequired);
+
+        client.commit_play_session(&user, &3, &play_commitment(&env, &session_id, 900, 300));
+        env.ledger().with_mut(|l| l.timestamp = START + 300);
+        let amount = client.reward_activity_with_proof(&admin, &user, &3, &session_id, &900, &300);
+        assert_eq!(amount, 5 * UNIT);
+        assert_eq!(client.get_balance(&user), 5 * UNIT);
+
+        // The commitment is consumed by the reveal
+        let replay = client.try_reward_activity_with_proof(&admin, &user, &3, &session_id, &900, &300);
+        assert!(replay.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Session commitment not found")]
+    fn test_proof_of_play_hash_mismatch() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.set_proof_of_play(&3, &true, &120);
+        let session_id = BytesN::from_array(&env, &[7; 32]);
+
+        client.commit_play_session(&user, &3, &play_commitment(&env, &session_id, 900, 300));
+        env.ledger().with_mut(|l| l.timestamp = START + 300);
+        client.reward_activity_with_proof(&admin, &user, &3, &session_id, &9_000, &300);
+    }
+
+    #[test]
+    #[should_panic(expected = "Session too short")]
+    fn test_proof_of_play_too_short() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.set_proof_of_play(&3, &true, &120);
+        let session_id = BytesN::from_array(&env, &[7; 32]);
+
+        client.commit_play_session(&user, &3, &play_commitment(&env, &session_id, 900, 60));
+        env.ledger().with_mut(|l| l.timestamp = START + 60);
+        client.reward_activity_with_proof(&admin, &user, &3, &session_id, &900, &60);
+    }
+
+    #[test]
+    #[should_panic(expected = "Session duration has not elapsed")]
+    fn test_proof_of_play_duration_not_elapsed() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.set_proof_of_play(&3, &true, &120);
+        let session_id = BytesN::from_array(&env, &[7; 32]);
+
+        client.commit_play_session(&user, &3, &play_commitment(&env, &session_id, 900, 300));
+        env.ledger().with_mut(|l| l.timestamp = START + 299);
+        client.reward_activity_with_proof(&admin, &user, &3, &session_id, &900, &300);
+    }
+
+    #[test]
+    fn test_subscription_renews_then_lapses() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.award_credits(&admin, &user, &(25 * UNIT), &String::from_str(&env, "award"));
+        let plan_id = client.create_plan(&String::from_str(&env, "Pro"), &(10 * UNIT), &86_400, &0);
+
+        client.subscribe(&user, &plan_id);
+        assert!(client.is_subscribed(&user, &plan_id));
+        assert_eq!(client.charge_due(&user, &plan_id), SubscriptionStatus::Active);
+        assert_eq!(client.get_balance(&user), 15 * UNIT);
+
+        env.ledger().with_mut(|l| l.timestamp = START + 86_400);
+        assert_eq!(client.charge_due(&user, &plan_id), SubscriptionStatus::Active);
+        assert_eq!(client.get_balance(&user), 5 * UNIT);
+
+        // Not enough credits for the third period
+        env.ledger().with_mut(|l| l.timestamp = START + 2 * 86_400);
+        assert_eq!(client.charge_due(&user, &plan_id), SubscriptionStatus::Expired);
+        assert_eq!(client.get_subscription(&user, &plan_id).unwrap().status, SubscriptionStatus::Expired);
+        assert!(!client.is_subscribed(&user, &plan_id));
+        assert_eq!(client.get_balance(&user), 5 * UNIT);
+        assert_eq!(client.charge_due(&user, &plan_id), SubscriptionStatus::Expired);
+    }
+
+    #[test]
+    fn test_subscription_lapses_when_category_becomes_host_sink() {
+        let (env, client, admin) = setup();
+        let user = Address::generate(&env);
+        client.award_credits(&admin, &user, &(25 * UNIT), &String::from_str(&env, "award"));
+        let plan_id = client.create_plan(&String::from_str(&env, "Pro"), &(10 * UNIT), &86_400, &3);
+        client.subscribe(&user, &plan_id);
+
+        // Renewals have no beneficiary to pay a host with
+        client.set_spend_sink(&3, &SpendSink::Host);
+        env.ledger().with_mut(|l| l.timestamp = START + 86_400);
+        assert_eq!(client.charge_due(&user, &plan_id), SubscriptionStatus::Expired);
+        assert!(!client.is_subscribed(&user, &plan_id));
+        assert_eq!(client.get_balance(&user), 15 * UNIT);
+    }
+
+    #[test]
+    #[should_panic(expected = "Plans cannot use a Host-sink category")]
+    fn test_plan_rejects_host_sink_category() {
+        let (env, client, _admin) = setup();
+        client.set_spend_sink(&3, &SpendSink::Host);
+        client.create_plan(&String::from_str(&env, "Pro"), &(10 * UNIT), &86_400, &3);
+    }
+
+    fn voucher_hash(env: &Env, code: &str) -> BytesN<32> {
+        env.crypto().sha256(&Bytes::from_slice(env, code.as_bytes())).into()
+    }
+
+    #[test]
+    fn test_voucher_redeem_once() {
+        let (env, client, _admin) = setup();
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        let batch_id = client.create_voucher_batch(&(START + 86_400), &10);
+        client.add_vouchers(&batch_id, &Vec::from_array(&env, [(voucher_hash(&env, "GIFT-1"), 50 * UNIT)]));
+
+        let code = Bytes::from_slice(&env, b"GIFT-1");
+        assert_eq!(client.redeem_voucher(&user, &code), 50 * UNIT);
+        assert_eq!(client.get_balance(&user), 50 * UNIT);
+        assert!(client.get_voucher(&voucher_hash(&env, "GIFT-1")).unwrap().is_redeemed);
+
+        assert!(client.try_redeem_voucher(&user, &code).is_err());
+        assert!(client.try_redeem_voucher(&other, &code).is_err());
+        let batch = client.get_voucher_batch(&batch_id);
+        assert_eq!(batch.redeemed_count, 1);
+        assert_eq!(batch.redeemed_credits, 50 * UNIT);
+    }
+
+    #[test]
+    #[should_panic(expected = "Voucher batch fully redeemed")]
+    fn test_voucher_redemption_cap() {
+        let (env, client, _admin) = setup();
+        let batch_id = client.create_voucher_batch(&(START + 86_400), &2);
+        client.add_vouchers(&batch_id, &Vec::from_array(&env, [
+            (voucher_hash(&env, "A"), UNIT),
+            (voucher_hash(&env, "B"), UNIT),
+            (voucher_hash(&env, "C"), UNIT),
+        ]));
+
+        client.redeem_voucher(&Address::generate(&env), &Bytes::from_slice(&env, b"A"));
+        client.redeem_voucher(&Address::generate(&env), &Bytes::from_slice(&env, b"B"));
+        client.redeem_voucher(&Address::generate(&env), &Bytes::from_slice(&env, b"C"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Voucher expired")]
+    fn test_voucher_expired() {
+        let (env, client, _admin) = setup();
+        let batch_id = client.create_voucher_batch(&(START + 86_400), &10);
+        client.add_vouchers(&batch_id, &Vec::from_array(&env, [(voucher_hash(&env, "GIFT-1"), UNIT)]));
+
+        env.ledger().with_mut(|l| l.timestamp = START + 86_400);
+        client.redeem_voucher(&Address::generate(&env), &Bytes::from_slice(&env, b"GIFT-1"));
+    }
+
+    fn fund_usdc(env: &Env, client: &ComputeCreditsContractClient, to: &Address, amount: i128) {
+        let usdc: Address = env.as_contract(&client.address, || {
+            env.storage().instance().get(&DataKey::USDCToken).unwrap()
+        });
+        soroban_sdk::token::StellarAssetClient::new(env, &usdc).mint(to, &amount);
+    }
+
+    #[test]
+    fn test_promotion_time_window() {
+        let (env, client, _admin) = setup();
+        let buyer = Address::generate(&env);
+        fund_usdc(&env, &client, &buyer, 10 * UNIT);
+        let promo_id = client.create_promotion(&1, &(START + 3600), &(START + 7200), &5000, &0, &0);
+
+        // Before the window: base package only
+        client.buy_credits(&buyer, &1);
+        assert_eq!(client.get_balance(&buyer), 100 * UNIT);
+
+        env.ledger().with_mut(|l| l.timestamp = START + 3600);
+        assert_eq!(client.get_active_promotions().len(), 1);
+        client.buy_credits(&buyer, &1);
+        assert_eq!(client.get_balance(&buyer), 250 * UNIT);
+        assert_eq!(client.get_promotion(&promo_id).sold_count, 1);
+
+        env.ledger().with_mut(|l| l.timestamp = START + 7200);
+        assert_eq!(client.get_active_promotions().len(), 0);
+        client.buy_credits(&buyer, &1);
+        assert_eq!(client.get_balance(&buyer), 350 * UNIT);
+    }
+
+    #[test]
+    fn test_promotion_rejects_bad_params() {
+        let (_env, client, _admin) = setup();
+        assert!(client.try_create_promotion(&1, &START, &(START + 3600), &10001, &0, &0).is_err());
+        assert!(client.try_create_promotion(&1, &(START + 3600), &(START + 3600), &5000, &0, &0).is_err());
+        assert!(client.try_create_promotion(&1, &(START + 7200), &(START + 3600), &5000, &0, &0).is_err());
+        assert_eq!(client.create_promotion(&1, &START, &(START + 3600), &10000, &0, &0), 1);
+    }
+
+    #[test]
+    fn test_promotion_per_user_limit() {
+        let (env, client, _admin) = setup();
+        let buyer = Address::generate(&env);
+        fund_usdc(&env, &client, &buyer, 10 * UNIT);
+        client.create_promotion(&1, &START, &(START + 3600), &5000, &1, &0);
+
+        client.buy_credits(&buyer, &1);
+        client.buy_credits(&buyer, &1);
+        assert_eq!(client.get_balance(&buyer), 250 * UNIT);
+    }
+
+    #[test]
+    fn test_promotion_global_cap() {
+        let (env, client, _admin) = setup();
+        let first = Address::generate(&env);
+        let second = Address::generate(&env);
+        let third = Address::generate(&env);
+        for buyer in [&first, &second, &third] {
+            fund_usdc(&env, &client, buyer, 10 * UNIT);
+        }
+        let promo_id = client.create_promotion(&1, &START, &(START + 3600), &5000, &0, &2);
+
+        client.buy_credits(&first, &1);
+        client.buy_credits(&second, &1);
+        client.buy_credits(&third, &1);
+        assert_eq!(client.get_balance(&first), 150 * UNIT);
+        assert_eq!(client.get_balance(&second), 150 * UNIT);
+        assert_eq!(client.get_balance(&third), 100 * UNIT);
+        assert_eq!(client.get_promotion(&promo_id).sold_count, 2);
+        assert_eq!(client.get_active_promotions().len(), 0);
+    }
+}
@@ -0,0 +1,39 @@
+//! Builds the LP share token wasm that pools deploy, so the tests can upload
+//! it without a separate manual build step.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let token_dir = manifest_dir.join("../lp-share-token");
+    println!("cargo:rerun-if-changed={}", token_dir.join("src").display());
+    println!("cargo:rerun-if-changed={}", token_dir.join("Cargo.toml").display());
+
+    // Only host-side tests upload the wasm
+    if env::var("CARGO_CFG_TARGET_ARCH").unwrap() == "wasm32" {
+        return;
+    }
+
+    // A separate target dir avoids blocking on the outer build's lock
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let target_dir = out_dir.join("target");
+    let status = Command::new(env::var("CARGO").unwrap())
+        .args(["build", "--release", "--target", "wasm32-unknown-unknown", "--manifest-path"])
+        .arg(token_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTC_WRAPPER")
+        .env_remove("RUSTC_WORKSPACE_WRAPPER")
+        .status()
+        .expect("failed to run cargo");
+    if !status.success() {
+        panic!("building lp-share-token for wasm32-unknown-unknown failed");
+    }
+
+    fs::copy(
+        target_dir.join("wasm32-unknown-unknown/release/lp_share_token.wasm"),
+        out_dir.join("lp_share_token.wasm"),
+    )
+    .unwrap();
+}
@@ -1,9 +1,26 @@
 #![no_std]
 
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short, token, Address, Env, Vec, BytesN,
+    contract, contractclient, contractimpl, contracttype, symbol_short, token, xdr::ToXdr, Address,
+    Bytes, Env, String, Vec, BytesN, U256,
 };
 
+/// Admin interface of the per-pool LP share token (see `lp-share-token`)
+#[contractclient(name = "ShareTokenClient")]
+pub trait ShareTokenInterface {
+    fn initialize(env: Env, admin: Address, decimals: u32, name: String, symbol: String);
+    fn mint(env: Env, to: Address, amount: i128);
+    fn burn(env: Env, from: Address, amount: i128);
+}
+
+/// Callback implemented by flash loan borrowers. The loaned `amount` of
+/// `token` is sent to the receiver before the call and `amount + fee` must be
+/// back in the pool contract when it returns.
+#[contractclient(name = "FlashLoanReceiverClient")]
+pub trait FlashLoanReceiver {
+    fn on_flash_loan(env: Env, token: Address, amount: i128, fee: i128, data: Bytes);
+}
+
 /// Pool pair configuration
 #[derive(Clone)]
 #[contracttype]
@@ -14,6 +31,37 @@ pub struct PoolConfig {
     pub reserve_b: i128,
     pub total_shares: i128,
     pub fee_bps: u32,  // Fee in basis points (e.g., 30 = 0.3%)
+    pub share_token: Address,  // SEP-41 LP share token for this pool
+    pub kind: PoolKind,
+}
+
+/// Pricing curve of a pool
+#[derive(Clone, PartialEq)]
+#[contracttype]
+pub enum PoolKind {
+    ConstantProduct,          // x * y = k
+    StableSwap(StableParams), // Curve-style invariant for pegged pairs
+    Concentrated(u32),        // Range positions over ticks; value is the tick spacing
+}
+
+/// Curve of a pool without its parameters, for lookups
+#[derive(Clone, Copy, PartialEq)]
+#[contracttype]
+pub enum PoolCurve {
+    ConstantProduct,
+    StableSwap,
+    Concentrated,
+}
+
+/// Amplification schedule of a StableSwap pool; A moves linearly from
+/// `initial_amp` at `ramp_start` to `future_amp` at `ramp_end`
+#[derive(Clone, PartialEq)]
+#[contracttype]
+pub struct StableParams {
+    pub initial_amp: u32,
+    pub future_amp: u32,
+    pub ramp_start: u64,
+    pub ramp_end: u64,
 }
 
 /// User's LP position
@@ -31,8 +79,22 @@ pub struct StakingPool {
     pub lp_token: BytesN<32>,
     pub reward_token: Address,
     pub total_staked: i128,
-    pub reward_rate: i128,      // Rewards per day
+    pub reward_rate: i128,      // Rewards per second
     pub lock_period: u64,       // Lock period in seconds
+    pub reward_per_share: i128, // Accumulated rewards per staked share, scaled by REWARD_PRECISION
+    pub last_update: u64,       // Last accumulator update
+    pub reward_budget: i128,    // Funded rewards not yet emitted
+    pub early_exit_penalty_bps: u32, // Share of LP forfeited when leaving inside lock_period
+    pub penalty_mode: PenaltyMode,
+    pub penalty_per_share: i128, // Redistributed penalty shares per staked share, scaled by REWARD_PRECISION
+}
+
+/// What happens to LP shares forfeited on early exit
+#[derive(Clone, PartialEq)]
+#[contracttype]
+pub enum PenaltyMode {
+    Burn,         // Burned, raising the value of every remaining LP share
+    Redistribute, // Credited to the remaining stakers
 }
 
 /// User's staking position
@@ -43,19 +105,179 @@ pub struct StakingPosition {
     pub staked_at: u64,
     pub last_claim: u64,
     pub rewards_claimed: i128,
+    pub reward_debt: i128,      // amount * reward_per_share at last settlement
+    pub unclaimed: i128,        // Settled but not yet claimed rewards
+    pub penalty_debt: i128,     // amount * penalty_per_share at last settlement
+}
+
+/// Live state of a concentrated-liquidity (range) pool
+#[derive(Clone)]
+#[contracttype]
+pub struct RangeState {
+    pub sqrt_price: U256,       // sqrt(token_a price in token_b), Q64.64
+    pub tick: i32,              // Tick containing sqrt_price
+    pub liquidity: i128,        // Liquidity of positions in range at the current tick
+    pub fee_growth_a: i128,     // LP fees per unit of liquidity, scaled by FEE_GROWTH_PRECISION
+    pub fee_growth_b: i128,
+}
+
+/// Per-tick bookkeeping for range positions
+#[derive(Clone)]
+#[contracttype]
+pub struct RangeTick {
+    pub liquidity_gross: i128,  // Liquidity of all positions bounded by this tick
+    pub liquidity_net: i128,    // Liquidity added when the price crosses upwards
+    pub fee_growth_outside_a: i128,
+    pub fee_growth_outside_b: i128,
+}
+
+/// A range order; ids are unique across pools and transferable like an NFT
+#[derive(Clone)]
+#[contracttype]
+pub struct RangePosition {
+    pub owner: Address,
+    pub pool_id: BytesN<32>,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: i128,
+    pub fee_growth_inside_a: i128, // Fee growth inside the range at last update
+    pub fee_growth_inside_b: i128,
+    pub fees_owed_a: i128,
+    pub fees_owed_b: i128,
+}
+
+/// Cumulative swap fee accounting for a pool, in token units
+#[derive(Clone)]
+#[contracttype]
+pub struct PoolFees {
+    pub total_fees_a: i128,     // All fees charged on token_a input
+    pub total_fees_b: i128,
+    pub protocol_fees_a: i128,  // Protocol share of the above, ever accrued
+    pub protocol_fees_b: i128,
+    pub uncollected_a: i128,    // Protocol fees held outside reserves awaiting collection
+    pub uncollected_b: i128,
 }
 
+/// Swap activity of a pool within one hour
+#[derive(Clone)]
+#[contracttype]
+pub struct StatsBucket {
+    pub hour: u64,              // Ledger timestamp / 3600
+    pub volume_a: i128,         // Swap input in token_a
+    pub volume_b: i128,
+    pub fees_a: i128,           // LP fees (net of protocol fees) in token_a
+    pub fees_b: i128,
+}
+
+/// Rolling 24h analytics of a pool; values are in token units, with
+/// `price_a` and `tvl_b` expressed in token_b
+#[derive(Clone)]
+#[contracttype]
+pub struct PoolStats {
+    pub reserve_a: i128,
+    pub reserve_b: i128,
+    pub total_shares: i128,
+    pub price_a: i128,          // token_a spot price in token_b, scaled by PRICE_PRECISION
+    pub tvl_b: i128,
+    pub volume_24h_a: i128,
+    pub volume_24h_b: i128,
+    pub fees_24h_a: i128,
+    pub fees_24h_b: i128,
+    pub fee_apr_bps: i128,      // Annualized LP fee yield on TVL
+}
+
+/// Running price accumulators for a pool's TWAP oracle
+#[derive(Clone)]
+#[contracttype]
+pub struct OracleState {
+    pub price_a_cumulative: i128, // Sum of (token_a spot price in token_b) * seconds, scaled by PRICE_PRECISION
+    pub price_b_cumulative: i128, // Sum of (token_b spot price in token_a) * seconds, scaled by PRICE_PRECISION
+    pub last_update: u64,
+    pub index: u32,               // Ring buffer slot of the latest observation
+    pub count: u32,               // Filled ring buffer slots
+}
+
+/// Snapshot of the accumulators at a point in time
+#[derive(Clone)]
+#[contracttype]
+pub struct Observation {
+    pub timestamp: u64,
+    pub price_a_cumulative: i128,
+    pub price_b_cumulative: i128,
+}
+
+/// Fixed-point scale for oracle prices
+const PRICE_PRECISION: i128 = 1_000_000_000_000;
+
+/// Observation ring buffer size per pool
+const OBSERVATION_CAPACITY: u32 = 120;
+
+/// Minimum spacing between stored observations (seconds)
+const OBSERVATION_INTERVAL: u64 = 60;
+
+/// Shares permanently locked by the first deposit of every pool
+const MINIMUM_LIQUIDITY: i128 = 1000;
+
+/// Hourly stats buckets kept per pool (a rolling 24h window)
+const STATS_BUCKET_SECONDS: u64 = 3600;
+const STATS_BUCKETS: u32 = 24;
+
+const SECONDS_PER_YEAR: i128 = 31_536_000;
+
+/// Amplification coefficient bounds and ramp limits for StableSwap pools
+const MAX_AMP: u32 = 10_000;
+const MAX_AMP_CHANGE: u32 = 10;
+const MIN_RAMP_TIME: u64 = 86_400;
+
+/// Tick bounds for range pools; sqrt prices stay within [2^-32, 2^32]
+const MIN_TICK: i32 = -443_636;
+const MAX_TICK: i32 = 443_636;
+const MAX_TICK_SPACING: u32 = 1000;
+
+/// floor(sqrt(1.0001) * 2^64), the Q64.64 sqrt-price ratio between adjacent ticks
+const SQRT_TICK_RATIO_Q64: u128 = 18_447_666_387_855_959_850;
+
+/// Fixed-point scale for range pool fee growth
+const FEE_GROWTH_PRECISION: i128 = 1_000_000_000_000_000_000;
+
+/// Per-pool swap fee bounds (bps)
+const MIN_FEE_BPS: u32 = 1;
+const MAX_FEE_BPS: u32 = 1000;
+
+/// Upper bound on the protocol's cut of swap fees (bps of the fee)
+const MAX_PROTOCOL_FEE_BPS: u32 = 5000;
+
+/// Fixed-point scale for the staking reward accumulator
+const REWARD_PRECISION: i128 = 1_000_000_000_000;
+
 /// Storage keys
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
     Admin,
     Pool(BytesN<32>),           // Pool ID -> PoolConfig
-    UserLP(Address, BytesN<32>), // User + Pool ID -> LPPosition
+    UserLP(Address, BytesN<32>), // User + Pool ID -> first deposit timestamp
     StakingPool(BytesN<32>),    // Staking Pool ID -> StakingPool
     UserStake(Address, BytesN<32>), // User + Pool ID -> StakingPosition
     TotalPools,
     Initialized,
+    PoolIndex(u32),             // Registry index -> Pool ID
+    PairPools(Address, Address), // Canonical token pair -> Pool IDs (one per fee tier)
+    FeeTiers,                   // Enabled fee tiers (bps)
+    ShareTokenWasm,             // Wasm hash deployed as each pool's share token
+    Treasury,                   // Recipient of collected protocol fees
+    ProtocolFeeBps,             // Protocol share of swap fees (bps of the fee, 0 = off)
+    PoolFees(BytesN<32>),       // Pool ID -> PoolFees
+    Oracle(BytesN<32>),         // Pool ID -> OracleState
+    Observation(BytesN<32>, u32), // Pool ID + ring slot -> Observation
+    RangePool(BytesN<32>),      // Pool ID -> RangeState
+    RangeTick(BytesN<32>, i32), // Pool ID + tick -> RangeTick
+    RangeTicks(BytesN<32>),     // Pool ID -> initialized ticks, ascending
+    Position(u64),              // Position ID -> RangePosition
+    NextPositionId,
+    OwnerPositions(Address),    // Owner -> Position IDs
+    FlashLoanLock,              // Set while a flash loan callback runs
+    StatsBucket(BytesN<32>, u32), // Pool ID + hour slot -> StatsBucket
 }
 
 /// Liquidity Pool Contract
@@ -65,7 +287,7 @@ pub struct LiquidityPool;
 #[contractimpl]
 impl LiquidityPool {
     /// Initialize the liquidity pool contract
-    pub fn initialize(env: Env, admin: Address) {
+    pub fn initialize(env: Env, admin: Address, share_token_wasm: BytesN<32>) {
         if env.storage().instance().has(&DataKey::Initialized) {
             panic!("already initialized");
         }
@@ -73,11 +295,20 @@ impl LiquidityPool {
         admin.require_auth();
         
         env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::ShareTokenWasm, &share_token_wasm);
         env.storage().instance().set(&DataKey::TotalPools, &0u32);
         env.storage().instance().set(&DataKey::Initialized, &true);
+        
+        // Default fee tiers: 0.05%, 0.3%, 1%
+        let mut fee_tiers = Vec::new(&env);
+        fee_tiers.push_back(5u32);
+        fee_tiers.push_back(30u32);
+        fee_tiers.push_back(100u32);
+        env.storage().instance().set(&DataKey::FeeTiers, &fee_tiers);
     }
     
-    /// Create a new liquidity pool
+    /// Create a new liquidity pool. Tokens are stored in canonical order, and
+    /// each pair may have one pool per enabled fee tier.
     pub fn create_pool(
         env: Env,
         token_a: Address,
@@ -87,367 +318,3722 @@ impl LiquidityPool {
         let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
         admin.require_auth();
         
-        // Generate pool ID from token addresses
-        let pool_id = env.crypto().sha256(&(token_a.clone(), token_b.clone()).into());
+        Self::register_pool(&env, token_a, token_b, fee_bps, PoolKind::ConstantProduct)
+    }
+    
+    /// Create a StableSwap pool for a pegged pair with amplification `amp` (admin only)
+    pub fn create_stable_pool(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+        fee_bps: u32,
+        amp: u32,
+    ) -> BytesN<32> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
         
-        let config = PoolConfig {
-            token_a: token_a.clone(),
-            token_b: token_b.clone(),
-            reserve_a: 0,
-            reserve_b: 0,
-            total_shares: 0,
-            fee_bps,
+        if amp == 0 || amp > MAX_AMP {
+            panic!("invalid amp");
+        }
+        
+        let now = env.ledger().timestamp();
+        let params = StableParams {
+            initial_amp: amp,
+            future_amp: amp,
+            ramp_start: now,
+            ramp_end: now,
         };
+        Self::register_pool(&env, token_a, token_b, fee_bps, PoolKind::StableSwap(params))
+    }
+    
+    /// Start ramping a StableSwap pool's A towards `future_amp` by `ramp_end` (admin only)
+    pub fn ramp_amp(env: Env, pool_id: BytesN<32>, future_amp: u32, ramp_end: u64) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
         
-        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        let current = Self::get_amp(env.clone(), pool_id.clone());
+        let now = env.ledger().timestamp();
         
-        let total: u32 = env.storage().instance().get(&DataKey::TotalPools).unwrap_or(0);
-        env.storage().instance().set(&DataKey::TotalPools, &(total + 1));
+        if future_amp == 0 || future_amp > MAX_AMP {
+            panic!("invalid amp");
+        }
+        if ramp_end < now + MIN_RAMP_TIME {
+            panic!("ramp too short");
+        }
+        if future_amp > current * MAX_AMP_CHANGE || future_amp * MAX_AMP_CHANGE < current {
+            panic!("amp change too large");
+        }
+        
+        config.kind = PoolKind::StableSwap(StableParams {
+            initial_amp: current,
+            future_amp,
+            ramp_start: now,
+            ramp_end,
+        });
+        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
         
         env.events().publish(
-            (symbol_short!("pool_new"), token_a, token_b),
-            fee_bps,
+            (symbol_short!("ramp_amp"), pool_id),
+            (current, future_amp, ramp_end),
+        );
+    }
+    
+    /// Freeze a StableSwap pool's A at its current value (admin only)
+    pub fn stop_ramp_amp(env: Env, pool_id: BytesN<32>) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        let current = Self::get_amp(env.clone(), pool_id.clone());
+        let now = env.ledger().timestamp();
+        
+        config.kind = PoolKind::StableSwap(StableParams {
+            initial_amp: current,
+            future_amp: current,
+            ramp_start: now,
+            ramp_end: now,
+        });
+        env.storage().persistent().set(&DataKey::Pool(pool_id), &config);
+    }
+    
+    /// Get a StableSwap pool's current amplification coefficient
+    pub fn get_amp(env: Env, pool_id: BytesN<32>) -> u32 {
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id))
+            .expect("pool not found");
+        match &config.kind {
+            PoolKind::StableSwap(params) => Self::stable_amp(&env, params),
+            _ => panic!("not a stable pool"),
+        }
+    }
+    
+    /// Create a concentrated-liquidity pool where LPs provide liquidity over
+    /// tick ranges. `initial_price` is token_a's price in token_b, scaled by
+    /// PRICE_PRECISION (admin only).
+    pub fn create_range_pool(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+        fee_bps: u32,
+        tick_spacing: u32,
+        initial_price: i128,
+    ) -> BytesN<32> {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        if tick_spacing == 0 || tick_spacing > MAX_TICK_SPACING {
+            panic!("invalid tick spacing");
+        }
+        if initial_price <= 0 {
+            panic!("invalid price");
+        }
+        
+        // Express the price in canonical (sorted) token order
+        let price = if token_a < token_b {
+            initial_price
+        } else {
+            Self::mul_div(&env, PRICE_PRECISION, PRICE_PRECISION, initial_price)
+        };
+        let sqrt_price = Self::sqrt(
+            &env,
+            Self::u256(&env, price).shl(128).div(&Self::u256(&env, PRICE_PRECISION)),
         );
+        if sqrt_price < Self::sqrt_price_at_tick(&env, MIN_TICK) || sqrt_price > Self::sqrt_price_at_tick(&env, MAX_TICK) {
+            panic!("price out of range");
+        }
+        
+        let pool_id = Self::register_pool(&env, token_a, token_b, fee_bps, PoolKind::Concentrated(tick_spacing));
+        
+        let state = RangeState {
+            tick: Self::tick_at_sqrt_price(&env, &sqrt_price, MIN_TICK, MAX_TICK),
+            sqrt_price,
+            liquidity: 0,
+            fee_growth_a: 0,
+            fee_growth_b: 0,
+        };
+        env.storage().persistent().set(&DataKey::RangePool(pool_id.clone()), &state);
+        env.storage().persistent().set(&DataKey::RangeTicks(pool_id.clone()), &Vec::<i32>::new(&env));
+        
+        let config: PoolConfig = env.storage().persistent().get(&DataKey::Pool(pool_id.clone())).unwrap();
+        Self::update_oracle(&env, &pool_id, &config);
         
         pool_id
     }
     
-    /// Add liquidity to a pool
-    pub fn add_liquidity(
+    /// Open an empty range position on [tick_lower, tick_upper)
+    pub fn open_position(
         env: Env,
         user: Address,
         pool_id: BytesN<32>,
-        amount_a: i128,
-        amount_b: i128,
-        min_shares: i128,
-    ) -> i128 {
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> u64 {
         user.require_auth();
         
-        let mut config: PoolConfig = env.storage().persistent()
+        let config: PoolConfig = env.storage().persistent()
             .get(&DataKey::Pool(pool_id.clone()))
             .expect("pool not found");
+        let spacing = match config.kind {
+            PoolKind::Concentrated(spacing) => spacing as i32,
+            _ => panic!("not a range pool"),
+        };
         
-        // Calculate shares to mint
-        let shares = if config.total_shares == 0 {
-            // Initial liquidity
-            Self::sqrt((amount_a * amount_b) as u128) as i128
-        } else {
-            // Proportional to existing liquidity
-            let shares_a = (amount_a * config.total_shares) / config.reserve_a;
-            let shares_b = (amount_b * config.total_shares) / config.reserve_b;
-            if shares_a < shares_b { shares_a } else { shares_b }
+        if tick_lower >= tick_upper
+            || tick_lower < MIN_TICK
+            || tick_upper > MAX_TICK
+            || tick_lower % spacing != 0
+            || tick_upper % spacing != 0
+        {
+            panic!("invalid range");
+        }
+        
+        let position_id: u64 = env.storage().instance().get(&DataKey::NextPositionId).unwrap_or(0);
+        env.storage().instance().set(&DataKey::NextPositionId, &(position_id + 1));
+        
+        let position = RangePosition {
+            owner: user.clone(),
+            pool_id: pool_id.clone(),
+            tick_lower,
+            tick_upper,
+            liquidity: 0,
+            fee_growth_inside_a: 0,
+            fee_growth_inside_b: 0,
+            fees_owed_a: 0,
+            fees_owed_b: 0,
         };
+        env.storage().persistent().set(&DataKey::Position(position_id), &position);
         
-        if shares < min_shares {
+        let owner_key = DataKey::OwnerPositions(user.clone());
+        let mut owned: Vec<u64> = env.storage().persistent().get(&owner_key).unwrap_or(Vec::new(&env));
+        owned.push_back(position_id);
+        env.storage().persistent().set(&owner_key, &owned);
+        
+        env.events().publish(
+            (symbol_short!("pos_open"), user, pool_id),
+            (position_id, tick_lower, tick_upper),
+        );
+        
+        position_id
+    }
+    
+    /// Add liquidity to a range position, pulling only what the range needs
+    /// at the current price
+    pub fn increase_liquidity(
+        env: Env,
+        user: Address,
+        position_id: u64,
+        amount_a_desired: i128,
+        amount_b_desired: i128,
+        min_liquidity: i128,
+    ) -> (i128, i128, i128) {
+        user.require_auth();
+        Self::check_unlocked(&env);
+        
+        if amount_a_desired < 0 || amount_b_desired < 0 {
+            panic!("invalid amount");
+        }
+        
+        let mut position = Self::owned_position(&env, &user, position_id);
+        let pool_id = position.pool_id.clone();
+        let mut config: PoolConfig = env.storage().persistent().get(&DataKey::Pool(pool_id.clone())).unwrap();
+        let mut state = Self::range_state(&env, &pool_id);
+        
+        let liquidity = Self::liquidity_for_amounts(
+            &env, &state, position.tick_lower, position.tick_upper, amount_a_desired, amount_b_desired,
+        );
+        if liquidity <= 0 {
+            panic!("insufficient liquidity minted");
+        }
+        if liquidity < min_liquidity {
             panic!("slippage too high");
         }
         
-        // Transfer tokens from user
-        let token_a_client = token::Client::new(&env, &config.token_a);
-        let token_b_client = token::Client::new(&env, &config.token_b);
+        let (amount_a, amount_b) = Self::amounts_for_liquidity(
+            &env, &state, position.tick_lower, position.tick_upper, liquidity, true,
+        );
         
-        token_a_client.transfer(&user, &env.current_contract_address(), &amount_a);
-        token_b_client.transfer(&user, &env.current_contract_address(), &amount_b);
+        if amount_a > 0 {
+            token::Client::new(&env, &config.token_a).transfer(&user, &env.current_contract_address(), &amount_a);
+        }
+        if amount_b > 0 {
+            token::Client::new(&env, &config.token_b).transfer(&user, &env.current_contract_address(), &amount_b);
+        }
         
-        // Update pool reserves
+        Self::modify_position(&env, &pool_id, &mut state, &mut position, liquidity);
         config.reserve_a += amount_a;
         config.reserve_b += amount_b;
-        config.total_shares += shares;
         
         env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
-        
-        // Update user LP position
-        let mut position: LPPosition = env.storage().persistent()
-            .get(&DataKey::UserLP(user.clone(), pool_id.clone()))
-            .unwrap_or(LPPosition {
-                shares: 0,
-                deposited_at: env.ledger().timestamp(),
-            });
-        
-        position.shares += shares;
-        env.storage().persistent().set(&DataKey::UserLP(user.clone(), pool_id.clone()), &position);
+        env.storage().persistent().set(&DataKey::RangePool(pool_id), &state);
+        env.storage().persistent().set(&DataKey::Position(position_id), &position);
         
         env.events().publish(
-            (symbol_short!("add_liq"), user, pool_id),
-            shares,
+            (symbol_short!("pos_inc"), user, position_id),
+            (liquidity, amount_a, amount_b),
         );
         
-        shares
+        (liquidity, amount_a, amount_b)
     }
     
-    /// Remove liquidity from a pool
-    pub fn remove_liquidity(
+    /// Remove liquidity from a range position and pay out the principal;
+    /// accrued fees stay owed to the position until collected
+    pub fn decrease_liquidity(
         env: Env,
         user: Address,
-        pool_id: BytesN<32>,
-        shares: i128,
+        position_id: u64,
+        liquidity: i128,
         min_a: i128,
         min_b: i128,
     ) -> (i128, i128) {
         user.require_auth();
+        Self::check_unlocked(&env);
         
-        let mut config: PoolConfig = env.storage().persistent()
-            .get(&DataKey::Pool(pool_id.clone()))
-            .expect("pool not found");
-        
-        let mut position: LPPosition = env.storage().persistent()
-            .get(&DataKey::UserLP(user.clone(), pool_id.clone()))
-            .expect("no LP position");
-        
-        if position.shares < shares {
-            panic!("insufficient shares");
+        let mut position = Self::owned_position(&env, &user, position_id);
+        if liquidity <= 0 || liquidity > position.liquidity {
+            panic!("insufficient liquidity");
         }
         
-        // Calculate tokens to return
-        let amount_a = (shares * config.reserve_a) / config.total_shares;
-        let amount_b = (shares * config.reserve_b) / config.total_shares;
+        let pool_id = position.pool_id.clone();
+        let mut config: PoolConfig = env.storage().persistent().get(&DataKey::Pool(pool_id.clone())).unwrap();
+        let mut state = Self::range_state(&env, &pool_id);
         
+        let (amount_a, amount_b) = Self::amounts_for_liquidity(
+            &env, &state, position.tick_lower, position.tick_upper, liquidity, false,
+        );
         if amount_a < min_a || amount_b < min_b {
             panic!("slippage too high");
         }
         
-        // Transfer tokens to user
-        let token_a_client = token::Client::new(&env, &config.token_a);
-        let token_b_client = token::Client::new(&env, &config.token_b);
-        
-        token_a_client.transfer(&env.current_contract_address(), &user, &amount_a);
-        token_b_client.transfer(&env.current_contract_address(), &user, &amount_b);
-        
-        // Update pool
+        Self::modify_position(&env, &pool_id, &mut state, &mut position, -liquidity);
         config.reserve_a -= amount_a;
         config.reserve_b -= amount_b;
-        config.total_shares -= shares;
         
         env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
+        env.storage().persistent().set(&DataKey::RangePool(pool_id), &state);
+        env.storage().persistent().set(&DataKey::Position(position_id), &position);
         
-        // Update user position
-        position.shares -= shares;
-        env.storage().persistent().set(&DataKey::UserLP(user.clone(), pool_id.clone()), &position);
+        if amount_a > 0 {
+            token::Client::new(&env, &config.token_a).transfer(&env.current_contract_address(), &user, &amount_a);
+        }
+        if amount_b > 0 {
+            token::Client::new(&env, &config.token_b).transfer(&env.current_contract_address(), &user, &amount_b);
+        }
         
         env.events().publish(
-            (symbol_short!("rem_liq"), user, pool_id),
-            shares,
+            (symbol_short!("pos_dec"), user, position_id),
+            (liquidity, amount_a, amount_b),
         );
         
         (amount_a, amount_b)
     }
     
-    /// Swap tokens
-    pub fn swap(
-        env: Env,
-        user: Address,
-        pool_id: BytesN<32>,
-        token_in: Address,
-        amount_in: i128,
-        min_out: i128,
-    ) -> i128 {
+    /// Pay out the swap fees a range position has earned
+    pub fn collect_fees(env: Env, user: Address, position_id: u64) -> (i128, i128) {
         user.require_auth();
         
-        let mut config: PoolConfig = env.storage().persistent()
-            .get(&DataKey::Pool(pool_id.clone()))
-            .expect("pool not found");
+        let mut position = Self::owned_position(&env, &user, position_id);
+        let pool_id = position.pool_id.clone();
+        let config: PoolConfig = env.storage().persistent().get(&DataKey::Pool(pool_id.clone())).unwrap();
+        let mut state = Self::range_state(&env, &pool_id);
         
-        let (reserve_in, reserve_out, is_a_to_b) = if token_in == config.token_a {
-            (config.reserve_a, config.reserve_b, true)
-        } else if token_in == config.token_b {
-            (config.reserve_b, config.reserve_a, false)
-        } else {
-            panic!("invalid token");
-        };
+        Self::modify_position(&env, &pool_id, &mut state, &mut position, 0);
         
-        // Calculate output with fee
-        let amount_in_with_fee = amount_in * (10000 - config.fee_bps as i128) / 10000;
-        let amount_out = (amount_in_with_fee * reserve_out) / (reserve_in + amount_in_with_fee);
+        let (fees_a, fees_b) = (position.fees_owed_a, position.fees_owed_b);
+        position.fees_owed_a = 0;
+        position.fees_owed_b = 0;
+        env.storage().persistent().set(&DataKey::Position(position_id), &position);
         
-        if amount_out < min_out {
-            panic!("slippage too high");
+        if fees_a > 0 {
+            token::Client::new(&env, &config.token_a).transfer(&env.current_contract_address(), &user, &fees_a);
+        }
+        if fees_b > 0 {
+            token::Client::new(&env, &config.token_b).transfer(&env.current_contract_address(), &user, &fees_b);
         }
         
-        // Transfer tokens
-        let token_in_client = token::Client::new(&env, &token_in);
-        token_in_client.transfer(&user, &env.current_contract_address(), &amount_in);
+        env.events().publish(
+            (symbol_short!("pos_fees"), user, position_id),
+            (fees_a, fees_b),
+        );
         
-        let token_out = if is_a_to_b { config.token_b.clone() } else { config.token_a.clone() };
-        let token_out_client = token::Client::new(&env, &token_out);
-        token_out_client.transfer(&env.current_contract_address(), &user, &amount_out);
+        (fees_a, fees_b)
+    }
+    
+    /// Hand a range position to a new owner
+    pub fn transfer_position(env: Env, from: Address, to: Address, position_id: u64) {
+        from.require_auth();
         
-        // Update reserves
-        if is_a_to_b {
-            config.reserve_a += amount_in;
-            config.reserve_b -= amount_out;
-        } else {
-            config.reserve_b += amount_in;
-            config.reserve_a -= amount_out;
-        }
+        let mut position = Self::owned_position(&env, &from, position_id);
+        position.owner = to.clone();
+        env.storage().persistent().set(&DataKey::Position(position_id), &position);
         
-        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
+        Self::remove_owned_position(&env, &from, position_id);
+        let to_key = DataKey::OwnerPositions(to.clone());
+        let mut owned: Vec<u64> = env.storage().persistent().get(&to_key).unwrap_or(Vec::new(&env));
+        owned.push_back(position_id);
+        env.storage().persistent().set(&to_key, &owned);
         
         env.events().publish(
-            (symbol_short!("swap"), user, pool_id),
-            (amount_in, amount_out),
+            (symbol_short!("pos_xfer"), from, to),
+            position_id,
         );
-        
-        amount_out
     }
     
-    /// Create LP staking pool
-    pub fn create_staking_pool(
-        env: Env,
-        pool_id: BytesN<32>,
-        reward_token: Address,
-        reward_rate: i128,
-        lock_period: u64,
-    ) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin.require_auth();
+    /// Delete an emptied range position with nothing left to collect
+    pub fn close_position(env: Env, user: Address, position_id: u64) {
+        user.require_auth();
         
-        let staking_pool = StakingPool {
-            lp_token: pool_id.clone(),
-            reward_token,
-            total_staked: 0,
-            reward_rate,
-            lock_period,
-        };
+        let position = Self::owned_position(&env, &user, position_id);
+        if position.liquidity != 0 || position.fees_owed_a != 0 || position.fees_owed_b != 0 {
+            panic!("position not empty");
+        }
         
-        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+        env.storage().persistent().remove(&DataKey::Position(position_id));
+        Self::remove_owned_position(&env, &user, position_id);
+        
+        env.events().publish(
+            (symbol_short!("pos_close"), user),
+            position_id,
+        );
     }
     
-    /// Stake LP tokens
-    pub fn stake_lp(env: Env, user: Address, pool_id: BytesN<32>, amount: i128) {
+    /// Get a range position
+    pub fn get_position(env: Env, position_id: u64) -> RangePosition {
+        env.storage().persistent()
+            .get(&DataKey::Position(position_id))
+            .expect("position not found")
+    }
+    
+    /// Get the range position IDs held by an owner
+    pub fn get_positions(env: Env, owner: Address) -> Vec<u64> {
+        env.storage().persistent()
+            .get(&DataKey::OwnerPositions(owner))
+            .unwrap_or(Vec::new(&env))
+    }
+    
+    /// Fees a range position could collect right now
+    pub fn get_position_fees(env: Env, position_id: u64) -> (i128, i128) {
+        let mut position = Self::get_position(env.clone(), position_id);
+        let pool_id = position.pool_id.clone();
+        let mut state = Self::range_state(&env, &pool_id);
+        Self::modify_position(&env, &pool_id, &mut state, &mut position, 0);
+        (position.fees_owed_a, position.fees_owed_b)
+    }
+    
+    /// Get a range pool's price, tick and active liquidity
+    pub fn get_range_state(env: Env, pool_id: BytesN<32>) -> RangeState {
+        Self::range_state(&env, &pool_id)
+    }
+    
+    /// Add liquidity to a pool. Only the amounts matching the current
+    /// reserve ratio are pulled, bounded by the desired and minimum amounts.
+    pub fn add_liquidity(
+        env: Env,
+        user: Address,
+        pool_id: BytesN<32>,
+        amount_a_desired: i128,
+        amount_b_desired: i128,
+        amount_a_min: i128,
+        amount_b_min: i128,
+    ) -> (i128, i128, i128) {
         user.require_auth();
+        Self::check_unlocked(&env);
         
-        let position: LPPosition = env.storage().persistent()
-            .get(&DataKey::UserLP(user.clone(), pool_id.clone()))
-            .expect("no LP position");
+        if amount_a_desired <= 0 || amount_b_desired <= 0 {
+            panic!("invalid amount");
+        }
         
-        if position.shares < amount {
-            panic!("insufficient LP tokens");
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        
+        let (amount_a, amount_b) = Self::optimal_amounts(
+            &env, &config, amount_a_desired, amount_b_desired, amount_a_min, amount_b_min,
+        );
+        
+        // Transfer tokens from user
+        let token_a_client = token::Client::new(&env, &config.token_a);
+        let token_b_client = token::Client::new(&env, &config.token_b);
+        
+        token_a_client.transfer(&user, &env.current_contract_address(), &amount_a);
+        token_b_client.transfer(&user, &env.current_contract_address(), &amount_b);
+        
+        let shares = Self::deposit(&env, &pool_id, &mut config, &user, amount_a, amount_b);
+        
+        env.events().publish(
+            (symbol_short!("add_liq"), user, pool_id),
+            (amount_a, amount_b, shares),
+        );
+        
+        (amount_a, amount_b, shares)
+    }
+    
+    /// Remove liquidity from a pool
+    pub fn remove_liquidity(
+        env: Env,
+        user: Address,
+        pool_id: BytesN<32>,
+        shares: i128,
+        min_a: i128,
+        min_b: i128,
+    ) -> (i128, i128) {
+        user.require_auth();
+        Self::check_unlocked(&env);
+        
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        
+        let (amount_a, amount_b) = Self::withdraw(&env, &pool_id, &mut config, &user, shares);
+        
+        if amount_a < min_a || amount_b < min_b {
+            panic!("slippage too high");
         }
         
-        let mut staking_pool: StakingPool = env.storage().persistent()
-            .get(&DataKey::StakingPool(pool_id.clone()))
-            .expect("staking pool not found");
+        // Transfer tokens to user
+        let token_a_client = token::Client::new(&env, &config.token_a);
+        let token_b_client = token::Client::new(&env, &config.token_b);
         
-        let current_time = env.ledger().timestamp();
+        token_a_client.transfer(&env.current_contract_address(), &user, &amount_a);
+        token_b_client.transfer(&env.current_contract_address(), &user, &amount_b);
         
-        // Create or update staking position
-        let mut stake: StakingPosition = env.storage().persistent()
-            .get(&DataKey::UserStake(user.clone(), pool_id.clone()))
-            .unwrap_or(StakingPosition {
-                amount: 0,
-                staked_at: current_time,
-                last_claim: current_time,
-                rewards_claimed: 0,
-            });
+        env.events().publish(
+            (symbol_short!("rem_liq"), user, pool_id),
+            shares,
+        );
         
-        stake.amount += amount;
-        staking_pool.total_staked += amount;
+        (amount_a, amount_b)
+    }
+    
+    /// Provide liquidity from a single token: swaps the fraction of `amount_in`
+    /// that balances the deposit, adds both sides and refunds any dust
+    pub fn zap_in(
+        env: Env,
+        user: Address,
+        pool_id: BytesN<32>,
+        token_in: Address,
+        amount_in: i128,
+        min_shares: i128,
+    ) -> i128 {
+        user.require_auth();
+        Self::check_unlocked(&env);
         
-        env.storage().persistent().set(&DataKey::UserStake(user.clone(), pool_id.clone()), &stake);
-        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+        if amount_in <= 0 {
+            panic!("invalid amount");
+        }
+        
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        if config.total_shares == 0 {
+            panic!("pool has no liquidity");
+        }
+        
+        let swap_amount = Self::zap_swap_amount(&env, &config, &token_in, amount_in);
+        
+        token::Client::new(&env, &token_in).transfer(&user, &env.current_contract_address(), &amount_in);
+        
+        // Swap inside the pool; the bought side never leaves the contract
+        let swapped = Self::quote_out(&env, &pool_id, &config, &token_in, swap_amount);
+        Self::apply_hop(&env, &pool_id, &mut config, &token_in, swap_amount, swapped);
+        
+        let remaining = amount_in - swap_amount;
+        let (desired_a, desired_b) = if token_in == config.token_a {
+            (remaining, swapped)
+        } else {
+            (swapped, remaining)
+        };
+        let (amount_a, amount_b) = Self::optimal_amounts(&env, &config, desired_a, desired_b, 0, 0);
+        
+        let shares = Self::deposit(&env, &pool_id, &mut config, &user, amount_a, amount_b);
+        if shares < min_shares {
+            panic!("slippage too high");
+        }
+        
+        // Refund whatever the pool ratio could not absorb
+        if desired_a > amount_a {
+            token::Client::new(&env, &config.token_a).transfer(&env.current_contract_address(), &user, &(desired_a - amount_a));
+        }
+        if desired_b > amount_b {
+            token::Client::new(&env, &config.token_b).transfer(&env.current_contract_address(), &user, &(desired_b - amount_b));
+        }
         
         env.events().publish(
-            (symbol_short!("stake"), user, pool_id),
-            amount,
+            (symbol_short!("zap_in"), user, pool_id),
+            (token_in, amount_in, shares),
         );
+        
+        shares
     }
     
-    /// Claim staking rewards
-    pub fn claim_staking_rewards(env: Env, user: Address, pool_id: BytesN<32>) -> i128 {
+    /// Remove liquidity and swap the other side, paying out a single token
+    pub fn zap_out(
+        env: Env,
+        user: Address,
+        pool_id: BytesN<32>,
+        shares: i128,
+        token_out: Address,
+        min_out: i128,
+    ) -> i128 {
         user.require_auth();
+        Self::check_unlocked(&env);
         
-        let staking_pool: StakingPool = env.storage().persistent()
-            .get(&DataKey::StakingPool(pool_id.clone()))
-            .expect("staking pool not found");
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        if token_out != config.token_a && token_out != config.token_b {
+            panic!("invalid token");
+        }
+        let token_other = Self::other_token(&config, &token_out);
         
-        let mut stake: StakingPosition = env.storage().persistent()
-            .get(&DataKey::UserStake(user.clone(), pool_id.clone()))
-            .expect("no staking position");
+        let (amount_a, amount_b) = Self::withdraw(&env, &pool_id, &mut config, &user, shares);
+        let (kept, to_swap) = if token_out == config.token_a {
+            (amount_a, amount_b)
+        } else {
+            (amount_b, amount_a)
+        };
         
-        let current_time = env.ledger().timestamp();
-        let seconds_per_day: u64 = 86400;
-        let time_elapsed = current_time - stake.last_claim;
-        let days_elapsed = time_elapsed / seconds_per_day;
+        let mut amount_out = kept;
+        if to_swap > 0 {
+            let swapped = Self::quote_out(&env, &pool_id, &config, &token_other, to_swap);
+            Self::apply_hop(&env, &pool_id, &mut config, &token_other, to_swap, swapped);
+            amount_out += swapped;
+        }
         
-        if days_elapsed == 0 {
-            panic!("no rewards available");
+        if amount_out < min_out {
+            panic!("slippage too high");
         }
         
-        // Calculate rewards proportional to stake
-        let user_share = (stake.amount * 10000) / staking_pool.total_staked;
-        let rewards = (staking_pool.reward_rate * days_elapsed as i128 * user_share) / 10000;
+        token::Client::new(&env, &token_out).transfer(&env.current_contract_address(), &user, &amount_out);
         
-        // Transfer rewards
-        let token_client = token::Client::new(&env, &staking_pool.reward_token);
-        token_client.transfer(&env.current_contract_address(), &user, &rewards);
+        env.events().publish(
+            (symbol_short!("zap_out"), user, pool_id),
+            (token_out, shares, amount_out),
+        );
         
-        stake.last_claim = current_time;
-        stake.rewards_claimed += rewards;
+        amount_out
+    }
+    
+    /// Swap tokens
+    pub fn swap(
+        env: Env,
+        user: Address,
+        pool_id: BytesN<32>,
+        token_in: Address,
+        amount_in: i128,
+        min_out: i128,
+    ) -> i128 {
+        user.require_auth();
+        Self::check_unlocked(&env);
         
-        env.storage().persistent().set(&DataKey::UserStake(user.clone(), pool_id.clone()), &stake);
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        
+        let amount_out = Self::quote_out(&env, &pool_id, &config, &token_in, amount_in);
+        
+        if amount_out < min_out {
+            panic!("slippage too high");
+        }
+        
+        // Transfer tokens
+        let token_out = Self::other_token(&config, &token_in);
+        let token_in_client = token::Client::new(&env, &token_in);
+        token_in_client.transfer(&user, &env.current_contract_address(), &amount_in);
+        
+        let token_out_client = token::Client::new(&env, &token_out);
+        token_out_client.transfer(&env.current_contract_address(), &user, &amount_out);
+        
+        Self::apply_hop(&env, &pool_id, &mut config, &token_in, amount_in, amount_out);
         
         env.events().publish(
-            (symbol_short!("stake_rw"), user, pool_id),
-            rewards,
+            (symbol_short!("swap"), user, pool_id),
+            (amount_in, amount_out),
         );
         
-        rewards
+        amount_out
     }
     
-    /// Get pool configuration
-    pub fn get_pool(env: Env, pool_id: BytesN<32>) -> PoolConfig {
-        env.storage().persistent().get(&DataKey::Pool(pool_id)).expect("pool not found")
+    /// Swap an exact input amount along a token path, routing each hop
+    /// through the pair's best-priced pool. A pool serves at most one hop.
+    pub fn swap_exact_in(
+        env: Env,
+        user: Address,
+        path: Vec<Address>,
+        amount_in: i128,
+        min_out: i128,
+        deadline: u64,
+    ) -> Vec<i128> {
+        user.require_auth();
+        Self::check_unlocked(&env);
+        Self::check_deadline(&env, deadline);
+        
+        let (pool_ids, amounts) = Self::route_out(&env, &path, amount_in);
+        let amount_out = amounts.get(amounts.len() - 1).unwrap();
+        
+        if amount_out < min_out {
+            panic!("slippage too high");
+        }
+        
+        Self::execute_route(&env, &user, &path, &pool_ids, &amounts);
+        
+        amounts
     }
     
-    /// Get user LP position
-    pub fn get_lp_position(env: Env, user: Address, pool_id: BytesN<32>) -> LPPosition {
-        env.storage().persistent()
-            .get(&DataKey::UserLP(user, pool_id))
-            .unwrap_or(LPPosition { shares: 0, deposited_at: 0 })
+    /// Swap for an exact output amount along a token path, spending at most
+    /// `max_in`. A pool serves at most one hop.
+    pub fn swap_exact_out(
+        env: Env,
+        user: Address,
+        path: Vec<Address>,
+        amount_out: i128,
+        max_in: i128,
+        deadline: u64,
+    ) -> Vec<i128> {
+        user.require_auth();
+        Self::check_unlocked(&env);
+        Self::check_deadline(&env, deadline);
+        
+        let (pool_ids, amounts) = Self::route_in(&env, &path, amount_out);
+        
+        if amounts.get(0).unwrap() > max_in {
+            panic!("excessive input amount");
+        }
+        
+        Self::execute_route(&env, &user, &path, &pool_ids, &amounts);
+        
+        amounts
     }
     
-    /// Get user staking position
-    pub fn get_staking_position(env: Env, user: Address, pool_id: BytesN<32>) -> StakingPosition {
-        env.storage().persistent()
-            .get(&DataKey::UserStake(user, pool_id))
-            .unwrap_or(StakingPosition {
-                amount: 0,
-                staked_at: 0,
-                last_claim: 0,
-                rewards_claimed: 0,
-            })
+    /// Quote the amount received at each step of a path for an exact input
+    pub fn get_amounts_out(env: Env, path: Vec<Address>, amount_in: i128) -> Vec<i128> {
+        Self::route_out(&env, &path, amount_in).1
     }
     
-    /// Get quote for swap
-    pub fn get_swap_quote(
+    /// Quote the amount required at each step of a path for an exact output
+    pub fn get_amounts_in(env: Env, path: Vec<Address>, amount_out: i128) -> Vec<i128> {
+        Self::route_in(&env, &path, amount_out).1
+    }
+    
+    /// Lend `amount` of one of a pool's tokens to `receiver` for the duration
+    /// of its `on_flash_loan` callback. The loan plus the pool's swap fee must
+    /// be repaid before the callback returns; the fee is credited to LPs.
+    pub fn flash_loan(
         env: Env,
+        receiver: Address,
         pool_id: BytesN<32>,
-        token_in: Address,
-        amount_in: i128,
+        token: Address,
+        amount: i128,
+        data: Bytes,
     ) -> i128 {
-        let config: PoolConfig = env.storage().persistent()
-            .get(&DataKey::Pool(pool_id))
+        receiver.require_auth();
+        Self::check_unlocked(&env);
+        
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
             .expect("pool not found");
         
-        let (reserve_in, reserve_out) = if token_in == config.token_a {
-            (config.reserve_a, config.reserve_b)
+        let (reserve, _) = Self::reserves_for(&config, &token);
+        if amount <= 0 || amount > reserve {
+            panic!("invalid amount");
+        }
+        
+        let fee = Self::mul_div_ceil(&env, amount, config.fee_bps as i128, 10000);
+        let token_client = token::Client::new(&env, &token);
+        let balance_before = token_client.balance(&env.current_contract_address());
+        
+        // Repayment is measured from the contract balance, so nothing else may
+        // move tokens in while the receiver holds the loan
+        env.storage().instance().set(&DataKey::FlashLoanLock, &true);
+        token_client.transfer(&env.current_contract_address(), &receiver, &amount);
+        FlashLoanReceiverClient::new(&env, &receiver).on_flash_loan(&token, &amount, &fee, &data);
+        env.storage().instance().remove(&DataKey::FlashLoanLock);
+        
+        let paid = token_client.balance(&env.current_contract_address()) - balance_before;
+        if paid < fee {
+            panic!("flash loan not repaid");
+        }
+        
+        Self::update_oracle(&env, &pool_id, &config);
+        
+        let protocol_fee_bps: u32 = env.storage().instance().get(&DataKey::ProtocolFeeBps).unwrap_or(0);
+        let mut protocol_fee = Self::mul_div(&env, paid, protocol_fee_bps as i128, 10000);
+        let is_a = token == config.token_a;
+        
+        match config.kind {
+            PoolKind::Concentrated(_) => {
+                // Credit in-range positions; with none active the protocol keeps it
+                let mut state = Self::range_state(&env, &pool_id);
+                if state.liquidity > 0 {
+                    let growth = Self::mul_div(&env, paid - protocol_fee, FEE_GROWTH_PRECISION, state.liquidity);
+                    if is_a {
+                        state.fee_growth_a += growth;
+                    } else {
+                        state.fee_growth_b += growth;
+                    }
+                    env.storage().persistent().set(&DataKey::RangePool(pool_id.clone()), &state);
+                } else {
+                    protocol_fee = paid;
+                }
+            }
+            _ => {
+                if is_a {
+                    config.reserve_a += paid - protocol_fee;
+                } else {
+                    config.reserve_b += paid - protocol_fee;
+                }
+                env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
+            }
+        }
+        
+        Self::record_stats(&env, &pool_id, &config, &token, 0, paid - protocol_fee);
+        
+        let mut fees = Self::get_pool_fees(env.clone(), pool_id.clone());
+        if is_a {
+            fees.total_fees_a += paid;
+            fees.protocol_fees_a += protocol_fee;
+            fees.uncollected_a += protocol_fee;
         } else {
-            (config.reserve_b, config.reserve_a)
-        };
+            fees.total_fees_b += paid;
+            fees.protocol_fees_b += protocol_fee;
+            fees.uncollected_b += protocol_fee;
+        }
+        env.storage().persistent().set(&DataKey::PoolFees(pool_id.clone()), &fees);
         
-        let amount_in_with_fee = amount_in * (10000 - config.fee_bps as i128) / 10000;
-        (amount_in_with_fee * reserve_out) / (reserve_in + amount_in_with_fee)
+        env.events().publish(
+            (symbol_short!("flash"), receiver, pool_id),
+            (token, amount, paid),
+        );
+        
+        paid
     }
     
-    // Helper: integer square root
-    fn sqrt(n: u128) -> u128 {
-        if n == 0 { return 0; }
-        let mut x = n;
-        let mut y = (x + 1) / 2;
-        while y < x {
-            x = y;
-            y = (x + n / x) / 2;
-        }
-        x
+    /// Create the LP staking pool of an existing pool (one per pool, admin only)
+    pub fn create_staking_pool(
+        env: Env,
+        pool_id: BytesN<32>,
+        reward_token: Address,
+        reward_rate: i128,
+        lock_period: u64,
+    ) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        if reward_rate < 0 {
+            panic!("invalid reward rate");
+        }
+        if !env.storage().persistent().has(&DataKey::Pool(pool_id.clone())) {
+            panic!("pool not found");
+        }
+        if env.storage().persistent().has(&DataKey::StakingPool(pool_id.clone())) {
+            panic!("staking pool already exists");
+        }
+        
+        let staking_pool = StakingPool {
+            lp_token: pool_id.clone(),
+            reward_token,
+            total_staked: 0,
+            reward_rate,
+            lock_period,
+            reward_per_share: 0,
+            last_update: env.ledger().timestamp(),
+            reward_budget: 0,
+            early_exit_penalty_bps: 0,
+            penalty_mode: PenaltyMode::Burn,
+            penalty_per_share: 0,
+        };
+        
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+    }
+    
+    /// Deposit reward tokens into a staking pool's emission budget
+    pub fn fund_staking_pool(env: Env, funder: Address, pool_id: BytesN<32>, amount: i128) {
+        funder.require_auth();
+        Self::check_unlocked(&env);
+        
+        if amount <= 0 {
+            panic!("invalid amount");
+        }
+        
+        let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+        
+        let token_client = token::Client::new(&env, &staking_pool.reward_token);
+        token_client.transfer(&funder, &env.current_contract_address(), &amount);
+        
+        staking_pool.reward_budget += amount;
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+        
+        env.events().publish(
+            (symbol_short!("stk_fund"), funder, pool_id),
+            amount,
+        );
+    }
+    
+    /// Change the per-second emission rate (admin only)
+    pub fn set_reward_rate(env: Env, pool_id: BytesN<32>, reward_rate: i128) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        if reward_rate < 0 {
+            panic!("invalid reward rate");
+        }
+        
+        // Accrue at the old rate up to now before switching
+        let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+        staking_pool.reward_rate = reward_rate;
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id), &staking_pool);
+    }
+    
+    /// Stake LP share tokens (escrowed by this contract)
+    pub fn stake_lp(env: Env, user: Address, pool_id: BytesN<32>, amount: i128) {
+        user.require_auth();
+        Self::check_unlocked(&env);
+        
+        if amount <= 0 {
+            panic!("invalid amount");
+        }
+        
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        
+        let share_token = token::Client::new(&env, &config.share_token);
+        if share_token.balance(&user) < amount {
+            panic!("insufficient LP tokens");
+        }
+        share_token.transfer(&user, &env.current_contract_address(), &amount);
+        
+        let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+        
+        let current_time = env.ledger().timestamp();
+        
+        // Create or update staking position, settling rewards earned so far
+        let mut stake: StakingPosition = env.storage().persistent()
+            .get(&DataKey::UserStake(user.clone(), pool_id.clone()))
+            .unwrap_or(StakingPosition {
+                amount: 0,
+                staked_at: current_time,
+                last_claim: current_time,
+                rewards_claimed: 0,
+                reward_debt: 0,
+                unclaimed: 0,
+                penalty_debt: 0,
+            });
+        Self::settle_stake(&env, &mut staking_pool, &mut stake);
+        
+        // Each deposit restarts the lock
+        stake.amount += amount;
+        stake.staked_at = current_time;
+        staking_pool.total_staked += amount;
+        Self::checkpoint_stake(&env, &staking_pool, &mut stake);
+        
+        env.storage().persistent().set(&DataKey::UserStake(user.clone(), pool_id.clone()), &stake);
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+        
+        env.events().publish(
+            (symbol_short!("stake"), user, pool_id),
+            amount,
+        );
+    }
+    
+    /// Claim staking rewards
+    pub fn claim_staking_rewards(env: Env, user: Address, pool_id: BytesN<32>) -> i128 {
+        user.require_auth();
+        
+        let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+        
+        let mut stake: StakingPosition = env.storage().persistent()
+            .get(&DataKey::UserStake(user.clone(), pool_id.clone()))
+            .expect("no staking position");
+        
+        Self::settle_stake(&env, &mut staking_pool, &mut stake);
+        
+        let rewards = stake.unclaimed;
+        if rewards == 0 {
+            panic!("no rewards available");
+        }
+        
+        // Transfer rewards
+        let token_client = token::Client::new(&env, &staking_pool.reward_token);
+        token_client.transfer(&env.current_contract_address(), &user, &rewards);
+        
+        stake.unclaimed = 0;
+        stake.last_claim = env.ledger().timestamp();
+        stake.rewards_claimed += rewards;
+        
+        env.storage().persistent().set(&DataKey::UserStake(user.clone(), pool_id.clone()), &stake);
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+        
+        env.events().publish(
+            (symbol_short!("stake_rw"), user, pool_id),
+            rewards,
+        );
+        
+        rewards
+    }
+    
+    /// Withdraw staked LP shares. Inside the lock period this is only allowed
+    /// when an early-exit penalty is configured, and the penalty is deducted.
+    pub fn unstake_lp(env: Env, user: Address, pool_id: BytesN<32>, amount: i128) -> i128 {
+        user.require_auth();
+        
+        if amount <= 0 {
+            panic!("invalid amount");
+        }
+        
+        let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+        
+        let mut stake: StakingPosition = env.storage().persistent()
+            .get(&DataKey::UserStake(user.clone(), pool_id.clone()))
+            .expect("no staking position");
+        
+        Self::settle_stake(&env, &mut staking_pool, &mut stake);
+        
+        if amount > stake.amount {
+            panic!("insufficient stake");
+        }
+        
+        let penalty = match Self::early_exit_penalty(&env, &staking_pool, &stake, amount) {
+            Some(penalty) => penalty,
+            None => panic!("stake locked"),
+        };
+        
+        stake.amount -= amount;
+        staking_pool.total_staked -= amount;
+        Self::checkpoint_stake(&env, &staking_pool, &mut stake);
+        Self::apply_exit_penalty(&env, &pool_id, &mut staking_pool, penalty);
+        
+        let returned = amount - penalty;
+        Self::transfer_staked_shares(&env, &pool_id, &user, returned);
+        
+        env.storage().persistent().set(&DataKey::UserStake(user.clone(), pool_id.clone()), &stake);
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+        
+        env.events().publish(
+            (symbol_short!("unstake"), user, pool_id),
+            (amount, penalty),
+        );
+        
+        returned
+    }
+    
+    /// Withdraw the whole stake without claiming, forfeiting all unclaimed
+    /// rewards back to the pool's budget. The lock applies as in `unstake_lp`:
+    /// inside it, exit needs a configured early-exit penalty, which is deducted.
+    pub fn emergency_withdraw(env: Env, user: Address, pool_id: BytesN<32>) -> i128 {
+        user.require_auth();
+        
+        let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+        
+        let mut stake: StakingPosition = env.storage().persistent()
+            .get(&DataKey::UserStake(user.clone(), pool_id.clone()))
+            .expect("no staking position");
+        
+        Self::settle_stake(&env, &mut staking_pool, &mut stake);
+        
+        let amount = stake.amount;
+        let penalty = match Self::early_exit_penalty(&env, &staking_pool, &stake, amount) {
+            Some(penalty) => penalty,
+            None => panic!("stake locked"),
+        };
+        
+        staking_pool.reward_budget += stake.unclaimed;
+        staking_pool.total_staked -= amount;
+        Self::apply_exit_penalty(&env, &pool_id, &mut staking_pool, penalty);
+        
+        let returned = amount - penalty;
+        Self::transfer_staked_shares(&env, &pool_id, &user, returned);
+        
+        env.storage().persistent().remove(&DataKey::UserStake(user.clone(), pool_id.clone()));
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id.clone()), &staking_pool);
+        
+        env.events().publish(
+            (symbol_short!("emrg_wd"), user, pool_id),
+            (amount, penalty, stake.unclaimed),
+        );
+        
+        returned
+    }
+    
+    /// Configure the early-exit penalty for a staking pool (admin only)
+    pub fn set_early_exit_penalty(env: Env, pool_id: BytesN<32>, penalty_bps: u32, mode: PenaltyMode) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        if penalty_bps > 10000 {
+            panic!("invalid penalty");
+        }
+        
+        let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+        staking_pool.early_exit_penalty_bps = penalty_bps;
+        staking_pool.penalty_mode = mode;
+        env.storage().persistent().set(&DataKey::StakingPool(pool_id), &staking_pool);
+    }
+    
+    /// Rewards a user could claim right now
+    pub fn pending_rewards(env: Env, user: Address, pool_id: BytesN<32>) -> i128 {
+        let stake: Option<StakingPosition> = env.storage().persistent()
+            .get(&DataKey::UserStake(user, pool_id.clone()));
+        match stake {
+            Some(mut stake) => {
+                let mut staking_pool = Self::load_staking_pool(&env, &pool_id);
+                Self::settle_stake(&env, &mut staking_pool, &mut stake);
+                stake.unclaimed
+            }
+            None => 0,
+        }
+    }
+    
+    /// Get staking pool state, with the accumulator brought up to date
+    pub fn get_staking_pool(env: Env, pool_id: BytesN<32>) -> StakingPool {
+        Self::load_staking_pool(&env, &pool_id)
+    }
+    
+    /// Enable an additional fee tier within [MIN_FEE_BPS, MAX_FEE_BPS] (admin only)
+    pub fn enable_fee_tier(env: Env, fee_bps: u32) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        if !(MIN_FEE_BPS..=MAX_FEE_BPS).contains(&fee_bps) {
+            panic!("fee out of bounds");
+        }
+        
+        let mut fee_tiers: Vec<u32> = env.storage().instance().get(&DataKey::FeeTiers).unwrap_or(Vec::new(&env));
+        if !fee_tiers.contains(fee_bps) {
+            fee_tiers.push_back(fee_bps);
+            env.storage().instance().set(&DataKey::FeeTiers, &fee_tiers);
+        }
+    }
+    
+    /// Move a pool to another enabled fee tier (admin only). The pair may not
+    /// already have a pool of the same curve on that tier. The pool keeps the
+    /// ID derived from its original tier; lookups and duplicate checks go by
+    /// its current fee, so the old tier is free for a new pool.
+    pub fn set_fee_bps(env: Env, pool_id: BytesN<32>, fee_bps: u32) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        let fee_tiers: Vec<u32> = env.storage().instance().get(&DataKey::FeeTiers).unwrap_or(Vec::new(&env));
+        if !fee_tiers.contains(fee_bps) {
+            panic!("fee tier not enabled");
+        }
+        
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        if let Some(existing) = Self::find_pair_pool(&env, &config.token_a, &config.token_b, fee_bps, Self::curve(&config.kind)) {
+            if existing != pool_id {
+                panic!("pool already exists");
+            }
+        }
+        config.fee_bps = fee_bps;
+        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
+        
+        env.events().publish(
+            (symbol_short!("set_fee"), pool_id),
+            fee_bps,
+        );
+    }
+    
+    /// Set the protocol's share of swap fees; 0 turns the fee switch off (admin only)
+    pub fn set_protocol_fee(env: Env, protocol_fee_bps: u32) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        if protocol_fee_bps > MAX_PROTOCOL_FEE_BPS {
+            panic!("protocol fee too high");
+        }
+        
+        env.storage().instance().set(&DataKey::ProtocolFeeBps, &protocol_fee_bps);
+    }
+    
+    /// Get the protocol's share of swap fees (bps of the fee)
+    pub fn get_protocol_fee(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::ProtocolFeeBps).unwrap_or(0)
+    }
+    
+    /// Set the protocol fee treasury (admin only)
+    pub fn set_treasury(env: Env, treasury: Address) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        env.storage().instance().set(&DataKey::Treasury, &treasury);
+    }
+    
+    /// Send a pool's uncollected protocol fees to the treasury
+    pub fn collect_protocol_fees(env: Env, pool_id: BytesN<32>) -> (i128, i128) {
+        let treasury: Address = env.storage().instance()
+            .get(&DataKey::Treasury)
+            .expect("treasury not set");
+        
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        let mut fees = Self::get_pool_fees(env.clone(), pool_id.clone());
+        
+        let (amount_a, amount_b) = (fees.uncollected_a, fees.uncollected_b);
+        if amount_a > 0 {
+            token::Client::new(&env, &config.token_a).transfer(&env.current_contract_address(), &treasury, &amount_a);
+        }
+        if amount_b > 0 {
+            token::Client::new(&env, &config.token_b).transfer(&env.current_contract_address(), &treasury, &amount_b);
+        }
+        
+        fees.uncollected_a = 0;
+        fees.uncollected_b = 0;
+        env.storage().persistent().set(&DataKey::PoolFees(pool_id.clone()), &fees);
+        
+        env.events().publish(
+            (symbol_short!("col_fees"), pool_id, treasury),
+            (amount_a, amount_b),
+        );
+        
+        (amount_a, amount_b)
+    }
+    
+    /// Get cumulative fee counters for a pool
+    pub fn get_pool_fees(env: Env, pool_id: BytesN<32>) -> PoolFees {
+        env.storage().persistent()
+            .get(&DataKey::PoolFees(pool_id))
+            .unwrap_or(PoolFees {
+                total_fees_a: 0,
+                total_fees_b: 0,
+                protocol_fees_a: 0,
+                protocol_fees_b: 0,
+                uncollected_a: 0,
+                uncollected_b: 0,
+            })
+    }
+    
+    /// Rolling 24h volume, fees and fee APR of a pool
+    pub fn get_pool_stats(env: Env, pool_id: BytesN<32>) -> PoolStats {
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        
+        let mut stats = PoolStats {
+            reserve_a: config.reserve_a,
+            reserve_b: config.reserve_b,
+            total_shares: config.total_shares,
+            price_a: 0,
+            tvl_b: 0,
+            volume_24h_a: 0,
+            volume_24h_b: 0,
+            fees_24h_a: 0,
+            fees_24h_b: 0,
+            fee_apr_bps: 0,
+        };
+        
+        let hour = env.ledger().timestamp() / STATS_BUCKET_SECONDS;
+        for slot in 0..STATS_BUCKETS {
+            let bucket: Option<StatsBucket> = env.storage().persistent()
+                .get(&DataKey::StatsBucket(pool_id.clone(), slot));
+            if let Some(bucket) = bucket {
+                if bucket.hour + (STATS_BUCKETS as u64) > hour {
+                    stats.volume_24h_a += bucket.volume_a;
+                    stats.volume_24h_b += bucket.volume_b;
+                    stats.fees_24h_a += bucket.fees_a;
+                    stats.fees_24h_b += bucket.fees_b;
+                }
+            }
+        }
+        
+        if Self::is_priced(&config) {
+            stats.price_a = Self::spot_price(&env, &pool_id, &config, &config.token_a);
+            stats.tvl_b = config.reserve_b + Self::mul_div(&env, config.reserve_a, stats.price_a, PRICE_PRECISION);
+        }
+        if stats.tvl_b > 0 {
+            let fees_b = stats.fees_24h_b + Self::mul_div(&env, stats.fees_24h_a, stats.price_a, PRICE_PRECISION);
+            stats.fee_apr_bps = Self::mul_div(&env, fees_b * 365, 10000, stats.tvl_b);
+        }
+        
+        stats
+    }
+    
+    /// Underlying token amounts of a user's stake in a pool: LP shares held
+    /// and staked, or range positions including uncollected fees
+    pub fn get_position_value(env: Env, user: Address, pool_id: BytesN<32>) -> (i128, i128) {
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        
+        if let PoolKind::Concentrated(_) = config.kind {
+            let state = Self::range_state(&env, &pool_id);
+            let (mut amount_a, mut amount_b) = (0, 0);
+            for position_id in Self::get_positions(env.clone(), user).iter() {
+                let mut position = Self::get_position(env.clone(), position_id);
+                if position.pool_id != pool_id {
+                    continue;
+                }
+                let (a, b) = Self::amounts_for_liquidity(
+                    &env, &state, position.tick_lower, position.tick_upper, position.liquidity, false,
+                );
+                Self::modify_position(&env, &pool_id, &mut state.clone(), &mut position, 0);
+                amount_a += a + position.fees_owed_a;
+                amount_b += b + position.fees_owed_b;
+            }
+            return (amount_a, amount_b);
+        }
+        
+        if config.total_shares == 0 {
+            return (0, 0);
+        }
+        let staked = Self::get_staking_position(env.clone(), user.clone(), pool_id.clone()).amount;
+        let shares = token::Client::new(&env, &config.share_token).balance(&user) + staked;
+        (
+            Self::mul_div(&env, shares, config.reserve_a, config.total_shares),
+            Self::mul_div(&env, shares, config.reserve_b, config.total_shares),
+        )
+    }
+    
+    /// Annualized staking reward yield in bps, valuing rewards and staked
+    /// shares in the pool's token_b at current spot prices. A foreign reward
+    /// token is priced in its deepest pool against either side. Zero when
+    /// nothing is staked or the reward token has no price.
+    pub fn get_staking_apr(env: Env, pool_id: BytesN<32>) -> i128 {
+        let staking_pool = Self::load_staking_pool(&env, &pool_id);
+        if staking_pool.total_staked == 0 {
+            return 0;
+        }
+        
+        let stats = Self::get_pool_stats(env.clone(), pool_id.clone());
+        if stats.total_shares == 0 {
+            return 0;
+        }
+        let staked_value = Self::mul_div(&env, staking_pool.total_staked, stats.tvl_b, stats.total_shares);
+        if staked_value == 0 {
+            return 0;
+        }
+        
+        // Emissions stop once the remaining budget is spent
+        let yearly = staking_pool.reward_rate.saturating_mul(SECONDS_PER_YEAR).min(staking_pool.reward_budget);
+        let config: PoolConfig = env.storage().persistent().get(&DataKey::Pool(pool_id)).unwrap();
+        let reward_price = match Self::price_in_token_b(&env, &config, stats.price_a, &staking_pool.reward_token) {
+            Some(price) => price,
+            None => return 0,
+        };
+        
+        Self::mul_div(&env, Self::mul_div(&env, yearly, reward_price, PRICE_PRECISION), 10000, staked_value)
+    }
+    
+    /// Value `amount_in` of `token_in` at the pool's time-weighted average
+    /// price over at least the last `window_seconds`
+    pub fn consult(env: Env, pool_id: BytesN<32>, token_in: Address, amount_in: i128, window_seconds: u64) -> i128 {
+        if window_seconds == 0 {
+            panic!("invalid window");
+        }
+        
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        let state: OracleState = env.storage().persistent()
+            .get(&DataKey::Oracle(pool_id.clone()))
+            .expect("no price history");
+        
+        let now = env.ledger().timestamp();
+        let current = Self::accumulate(&env, &pool_id, &state, &config, now);
+        
+        // Newest observation at or before the start of the window
+        let target = now.saturating_sub(window_seconds);
+        let mut start: Option<Observation> = None;
+        for slot in 0..state.count {
+            let obs: Observation = env.storage().persistent()
+                .get(&DataKey::Observation(pool_id.clone(), slot))
+                .unwrap();
+            if obs.timestamp <= target && start.as_ref().is_none_or(|s| obs.timestamp > s.timestamp) {
+                start = Some(obs);
+            }
+        }
+        let start = start.expect("window exceeds history");
+        
+        let elapsed = (now - start.timestamp) as i128;
+        let average = if token_in == config.token_a {
+            (current.price_a_cumulative - start.price_a_cumulative) / elapsed
+        } else if token_in == config.token_b {
+            (current.price_b_cumulative - start.price_b_cumulative) / elapsed
+        } else {
+            panic!("invalid token");
+        };
+        
+        amount_in * average / PRICE_PRECISION
+    }
+    
+    /// Get a pool's oracle accumulators
+    pub fn get_oracle_state(env: Env, pool_id: BytesN<32>) -> Option<OracleState> {
+        env.storage().persistent().get(&DataKey::Oracle(pool_id))
+    }
+    
+    /// Get enabled fee tiers
+    pub fn get_fee_tiers(env: Env) -> Vec<u32> {
+        env.storage().instance().get(&DataKey::FeeTiers).unwrap_or(Vec::new(&env))
+    }
+    
+    /// Get total number of pools
+    pub fn get_total_pools(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::TotalPools).unwrap_or(0)
+    }
+    
+    /// List pools in creation order, starting at `cursor`
+    pub fn list_pools(env: Env, cursor: u32, limit: u32) -> Vec<(BytesN<32>, PoolConfig)> {
+        let total: u32 = env.storage().instance().get(&DataKey::TotalPools).unwrap_or(0);
+        let end = cursor.saturating_add(limit).min(total);
+        let mut pools = Vec::new(&env);
+        for i in cursor..end {
+            let pool_id: BytesN<32> = env.storage().persistent().get(&DataKey::PoolIndex(i)).unwrap();
+            let config: PoolConfig = env.storage().persistent().get(&DataKey::Pool(pool_id.clone())).unwrap();
+            pools.push_back((pool_id, config));
+        }
+        pools
+    }
+    
+    /// Look up a pool by token pair (any order), current fee and curve
+    pub fn get_pool_by_tokens(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+        fee_bps: u32,
+        curve: PoolCurve,
+    ) -> Option<BytesN<32>> {
+        let (token_a, token_b) = Self::sort_tokens(token_a, token_b);
+        Self::find_pair_pool(&env, &token_a, &token_b, fee_bps, curve)
+    }
+    
+    /// Get all pools (one per fee tier) for a token pair
+    pub fn get_pair_pools(env: Env, token_a: Address, token_b: Address) -> Vec<BytesN<32>> {
+        let (token_a, token_b) = Self::sort_tokens(token_a, token_b);
+        env.storage().persistent()
+            .get(&DataKey::PairPools(token_a, token_b))
+            .unwrap_or(Vec::new(&env))
+    }
+    
+    /// Get pool configuration
+    pub fn get_pool(env: Env, pool_id: BytesN<32>) -> PoolConfig {
+        env.storage().persistent().get(&DataKey::Pool(pool_id)).expect("pool not found")
+    }
+    
+    /// Get user LP position (unstaked share token balance)
+    pub fn get_lp_position(env: Env, user: Address, pool_id: BytesN<32>) -> LPPosition {
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        LPPosition {
+            shares: token::Client::new(&env, &config.share_token).balance(&user),
+            deposited_at: env.storage().persistent().get(&DataKey::UserLP(user, pool_id)).unwrap_or(0),
+        }
+    }
+    
+    /// Get user staking position
+    pub fn get_staking_position(env: Env, user: Address, pool_id: BytesN<32>) -> StakingPosition {
+        env.storage().persistent()
+            .get(&DataKey::UserStake(user, pool_id))
+            .unwrap_or(StakingPosition {
+                amount: 0,
+                staked_at: 0,
+                last_claim: 0,
+                rewards_claimed: 0,
+                reward_debt: 0,
+                unclaimed: 0,
+                penalty_debt: 0,
+            })
+    }
+    
+    /// Get quote for swap
+    pub fn get_swap_quote(
+        env: Env,
+        pool_id: BytesN<32>,
+        token_in: Address,
+        amount_in: i128,
+    ) -> i128 {
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        
+        Self::quote_out(&env, &pool_id, &config, &token_in, amount_in)
+    }
+    
+    // Helper: reserves ordered as (in, out) for a swap from `token_in`
+    fn reserves_for(config: &PoolConfig, token_in: &Address) -> (i128, i128) {
+        if *token_in == config.token_a {
+            (config.reserve_a, config.reserve_b)
+        } else if *token_in == config.token_b {
+            (config.reserve_b, config.reserve_a)
+        } else {
+            panic!("invalid token");
+        }
+    }
+    
+    // Helper: the opposite side of a pair
+    fn other_token(config: &PoolConfig, token_in: &Address) -> Address {
+        if *token_in == config.token_a { config.token_b.clone() } else { config.token_a.clone() }
+    }
+    
+    // Helper: output for an exact input, fee taken from the input
+    fn quote_out(env: &Env, pool_id: &BytesN<32>, config: &PoolConfig, token_in: &Address, amount_in: i128) -> i128 {
+        if amount_in <= 0 {
+            panic!("invalid amount");
+        }
+        
+        let (reserve_in, reserve_out) = Self::reserves_for(config, token_in);
+        let amount_in_with_fee = Self::mul_div(env, amount_in, 10000 - config.fee_bps as i128, 10000);
+        match &config.kind {
+            PoolKind::ConstantProduct => {
+                Self::mul_div(env, amount_in_with_fee, reserve_out, reserve_in + amount_in_with_fee)
+            }
+            PoolKind::StableSwap(params) => {
+                if reserve_in == 0 || reserve_out == 0 {
+                    panic!("insufficient liquidity");
+                }
+                let amp = Self::stable_amp(env, params);
+                let d = Self::stable_d(env, reserve_in, reserve_out, amp);
+                let new_reserve_out = Self::stable_y(env, reserve_in + amount_in_with_fee, &d, amp);
+                // Round against the trader
+                (reserve_out - new_reserve_out - 1).max(0)
+            }
+            PoolKind::Concentrated(_) => {
+                Self::range_swap(env, pool_id, config, token_in, amount_in, false)
+                    .expect("insufficient liquidity").0
+            }
+        }
+    }
+    
+    // Helper: input required for an exact output, rounded up so that
+    // quote_out(quote_in(x)) >= x
+    fn quote_in(env: &Env, config: &PoolConfig, token_in: &Address, amount_out: i128) -> i128 {
+        if amount_out <= 0 {
+            panic!("invalid amount");
+        }
+        
+        let (reserve_in, reserve_out) = Self::reserves_for(config, token_in);
+        if amount_out >= reserve_out {
+            panic!("insufficient liquidity");
+        }
+        
+        let fee_factor = 10000 - config.fee_bps as i128;
+        let with_fee = match &config.kind {
+            PoolKind::ConstantProduct => {
+                Self::mul_div_ceil(env, reserve_in, amount_out, reserve_out - amount_out)
+            }
+            PoolKind::StableSwap(params) => {
+                let amp = Self::stable_amp(env, params);
+                let d = Self::stable_d(env, reserve_in, reserve_out, amp);
+                let new_reserve_in = Self::stable_y(env, reserve_out - amount_out, &d, amp);
+                // Covers the output rounding in quote_out and Newton's ±1
+                new_reserve_in - reserve_in + 2
+            }
+            PoolKind::Concentrated(_) => panic!("exact output not supported for range pools"),
+        };
+        Self::mul_div_ceil(env, with_fee, 10000, fee_factor)
+    }
+    
+    // Helper: marginal price of `token_in` in units of the other token, scaled by PRICE_PRECISION
+    fn spot_price(env: &Env, pool_id: &BytesN<32>, config: &PoolConfig, token_in: &Address) -> i128 {
+        let (reserve_in, reserve_out) = Self::reserves_for(config, token_in);
+        match &config.kind {
+            PoolKind::ConstantProduct => Self::mul_div(env, reserve_out, PRICE_PRECISION, reserve_in),
+            PoolKind::StableSwap(params) => {
+                // -dy/dx of the invariant: (4A'xy + D^3/x) / (4A'xy + D^3/y), A' = 4A
+                let amp = Self::stable_amp(env, params);
+                let d = Self::stable_d(env, reserve_in, reserve_out, amp);
+                let x = U256::from_u128(env, reserve_in as u128);
+                let y = U256::from_u128(env, reserve_out as u128);
+                let d3 = d.pow(3);
+                let cross = U256::from_u32(env, amp * 16).mul(&x).mul(&y);
+                let num = cross.add(&d3.div(&x));
+                let den = cross.add(&d3.div(&y));
+                num.mul(&U256::from_u128(env, PRICE_PRECISION as u128)).div(&den).to_u128().unwrap() as i128
+            }
+            PoolKind::Concentrated(_) => {
+                let state = Self::range_state(env, pool_id);
+                let squared = state.sqrt_price.mul(&state.sqrt_price);
+                let precision = Self::u256(env, PRICE_PRECISION);
+                if *token_in == config.token_a {
+                    Self::to_i128(squared.mul(&precision).shr(128))
+                } else {
+                    Self::to_i128(U256::from_u32(env, 1).shl(128).mul(&precision).div(&squared))
+                }
+            }
+        }
+    }
+    
+    // Helper: StableSwap A at the current point of its ramp
+    fn stable_amp(env: &Env, params: &StableParams) -> u32 {
+        let now = env.ledger().timestamp();
+        if now >= params.ramp_end {
+            return params.future_amp;
+        }
+        let elapsed = (now - params.ramp_start) as i128;
+        let duration = (params.ramp_end - params.ramp_start) as i128;
+        let delta = params.future_amp as i128 - params.initial_amp as i128;
+        (params.initial_amp as i128 + delta * elapsed / duration) as u32
+    }
+    
+    // Helper: two-coin StableSwap invariant D for balances x, y (Newton's method)
+    fn stable_d(env: &Env, x: i128, y: i128, amp: u32) -> U256 {
+        let zero = U256::from_u32(env, 0);
+        let one = U256::from_u32(env, 1);
+        let two = U256::from_u32(env, 2);
+        let three = U256::from_u32(env, 3);
+        let ann = U256::from_u32(env, amp * 4);
+        let x = U256::from_u128(env, x as u128);
+        let y = U256::from_u128(env, y as u128);
+        let s = x.add(&y);
+        if s == zero {
+            return zero;
+        }
+        
+        let mut d = s.clone();
+        for _ in 0..255 {
+            let d_p = d.mul(&d).div(&x.mul(&two)).mul(&d).div(&y.mul(&two));
+            let prev = d.clone();
+            d = ann.mul(&s).add(&d_p.mul(&two)).mul(&d)
+                .div(&ann.sub(&one).mul(&d).add(&d_p.mul(&three)));
+            let diff = if d > prev { d.sub(&prev) } else { prev.sub(&d) };
+            if diff <= one {
+                break;
+            }
+        }
+        d
+    }
+    
+    // Helper: the other balance that keeps invariant `d` when one side is `x`
+    fn stable_y(env: &Env, x: i128, d: &U256, amp: u32) -> i128 {
+        let one = U256::from_u32(env, 1);
+        let two = U256::from_u32(env, 2);
+        let ann = U256::from_u32(env, amp * 4);
+        let x = U256::from_u128(env, x as u128);
+        let c = d.mul(d).div(&x.mul(&two)).mul(d).div(&ann.mul(&two));
+        let b = x.add(&d.div(&ann));
+        
+        let mut y = d.clone();
+        for _ in 0..255 {
+            let prev = y.clone();
+            y = y.mul(&y).add(&c).div(&y.mul(&two).add(&b).sub(d));
+            let diff = if y > prev { y.sub(&prev) } else { prev.sub(&y) };
+            if diff <= one {
+                break;
+            }
+        }
+        y.to_u128().unwrap() as i128
+    }
+    
+    // Helper: a * b / c without intermediate overflow (non-negative operands)
+    fn mul_div(env: &Env, a: i128, b: i128, c: i128) -> i128 {
+        let product = U256::from_u128(env, a as u128).mul(&U256::from_u128(env, b as u128));
+        Self::to_i128(product.div(&U256::from_u128(env, c as u128)))
+    }
+    
+    // Helper: ceil(a * b / c) without intermediate overflow (non-negative operands)
+    fn mul_div_ceil(env: &Env, a: i128, b: i128, c: i128) -> i128 {
+        let c = U256::from_u128(env, c as u128);
+        let product = U256::from_u128(env, a as u128).mul(&U256::from_u128(env, b as u128));
+        Self::to_i128(product.add(&c).sub(&U256::from_u32(env, 1)).div(&c))
+    }
+    
+    // Helper: narrow a U256 result back to i128
+    fn to_i128(value: U256) -> i128 {
+        match value.to_u128() {
+            Some(v) if v <= i128::MAX as u128 => v as i128,
+            _ => panic!("math overflow"),
+        }
+    }
+    
+    // Helper: mint shares for tokens already received and book them into
+    // reserves; the first deposit locks MINIMUM_LIQUIDITY in the pool forever
+    fn deposit(
+        env: &Env,
+        pool_id: &BytesN<32>,
+        config: &mut PoolConfig,
+        to: &Address,
+        amount_a: i128,
+        amount_b: i128,
+    ) -> i128 {
+        if let PoolKind::Concentrated(_) = config.kind {
+            panic!("use range positions");
+        }
+        
+        let mut locked = 0;
+        let shares = if config.total_shares == 0 {
+            let liquidity = Self::sqrt(
+                env,
+                U256::from_u128(env, amount_a as u128).mul(&U256::from_u128(env, amount_b as u128)),
+            );
+            let liquidity = Self::to_i128(liquidity);
+            if liquidity <= MINIMUM_LIQUIDITY {
+                panic!("insufficient initial liquidity");
+            }
+            locked = MINIMUM_LIQUIDITY;
+            liquidity - MINIMUM_LIQUIDITY
+        } else {
+            // Proportional to existing liquidity
+            let shares_a = Self::mul_div(env, amount_a, config.total_shares, config.reserve_a);
+            let shares_b = Self::mul_div(env, amount_b, config.total_shares, config.reserve_b);
+            shares_a.min(shares_b)
+        };
+        
+        if shares <= 0 {
+            panic!("insufficient liquidity minted");
+        }
+        
+        Self::update_oracle(env, pool_id, config);
+        config.reserve_a += amount_a;
+        config.reserve_b += amount_b;
+        config.total_shares += shares + locked;
+        
+        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), config);
+        
+        let share_token = ShareTokenClient::new(env, &config.share_token);
+        if locked > 0 {
+            share_token.mint(&env.current_contract_address(), &locked);
+        }
+        share_token.mint(to, &shares);
+        
+        let deposit_key = DataKey::UserLP(to.clone(), pool_id.clone());
+        if !env.storage().persistent().has(&deposit_key) {
+            env.storage().persistent().set(&deposit_key, &env.ledger().timestamp());
+        }
+        
+        shares
+    }
+    
+    // Helper: burn `from`'s shares and take their slice out of reserves;
+    // the caller pays the tokens out
+    fn withdraw(
+        env: &Env,
+        pool_id: &BytesN<32>,
+        config: &mut PoolConfig,
+        from: &Address,
+        shares: i128,
+    ) -> (i128, i128) {
+        if let PoolKind::Concentrated(_) = config.kind {
+            panic!("use range positions");
+        }
+        if shares <= 0 {
+            panic!("invalid amount");
+        }
+        
+        let share_token = token::Client::new(env, &config.share_token);
+        if share_token.balance(from) < shares {
+            panic!("insufficient shares");
+        }
+        
+        let amount_a = Self::mul_div(env, shares, config.reserve_a, config.total_shares);
+        let amount_b = Self::mul_div(env, shares, config.reserve_b, config.total_shares);
+        
+        // Return and burn share tokens
+        share_token.transfer(from, &env.current_contract_address(), &shares);
+        share_token.burn(&env.current_contract_address(), &shares);
+        
+        Self::update_oracle(env, pool_id, config);
+        config.reserve_a -= amount_a;
+        config.reserve_b -= amount_b;
+        config.total_shares -= shares;
+        
+        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), config);
+        
+        (amount_a, amount_b)
+    }
+    
+    // Helper: how much of a single-token zap to swap so the rest matches the
+    // post-swap reserve ratio. Exact for constant product; StableSwap pools
+    // split by reserve weight, which is near-optimal around the peg.
+    fn zap_swap_amount(env: &Env, config: &PoolConfig, token_in: &Address, amount_in: i128) -> i128 {
+        let (reserve_in, reserve_out) = Self::reserves_for(config, token_in);
+        match &config.kind {
+            PoolKind::ConstantProduct => {
+                // s = (sqrt(r^2 (1e4 + F)^2 + 4e4 F a r) - r (1e4 + F)) / 2F, F = 1e4 - fee
+                let f = 10000 - config.fee_bps;
+                let r = U256::from_u128(env, reserve_in as u128);
+                let a = U256::from_u128(env, amount_in as u128);
+                let sum = r.mul(&U256::from_u32(env, 10000 + f));
+                let disc = sum.mul(&sum).add(&U256::from_u32(env, 40000).mul(&U256::from_u32(env, f)).mul(&a).mul(&r));
+                Self::to_i128(Self::sqrt(env, disc).sub(&sum).div(&U256::from_u32(env, 2 * f)))
+            }
+            PoolKind::StableSwap(_) => {
+                Self::mul_div(env, amount_in, reserve_out, reserve_in + reserve_out)
+            }
+            PoolKind::Concentrated(_) => panic!("use range positions"),
+        }
+    }
+    
+    // Helper: deposit amounts matching the pool ratio (Uniswap V2 style)
+    fn optimal_amounts(
+        env: &Env,
+        config: &PoolConfig,
+        amount_a_desired: i128,
+        amount_b_desired: i128,
+        amount_a_min: i128,
+        amount_b_min: i128,
+    ) -> (i128, i128) {
+        if config.reserve_a == 0 && config.reserve_b == 0 {
+            return (amount_a_desired, amount_b_desired);
+        }
+        
+        let amount_b_optimal = Self::mul_div(env, amount_a_desired, config.reserve_b, config.reserve_a);
+        if amount_b_optimal <= amount_b_desired {
+            if amount_b_optimal < amount_b_min {
+                panic!("insufficient b amount");
+            }
+            return (amount_a_desired, amount_b_optimal);
+        }
+        
+        let amount_a_optimal = Self::mul_div(env, amount_b_desired, config.reserve_a, config.reserve_b);
+        if amount_a_optimal < amount_a_min {
+            panic!("insufficient a amount");
+        }
+        (amount_a_optimal, amount_b_desired)
+    }
+    
+    // Helper: book a swap against a pool's reserves. The protocol's cut of
+    // the fee is held outside reserves until collected; the rest stays with LPs.
+    fn apply_hop(
+        env: &Env,
+        pool_id: &BytesN<32>,
+        config: &mut PoolConfig,
+        token_in: &Address,
+        amount_in: i128,
+        amount_out: i128,
+    ) {
+        Self::update_oracle(env, pool_id, config);
+        
+        let (fee, protocol_fee, retained) = match config.kind {
+            PoolKind::Concentrated(_) => {
+                let (out, fee, protocol_fee) = Self::range_swap(env, pool_id, config, token_in, amount_in, true)
+                    .expect("insufficient liquidity");
+                if out != amount_out {
+                    panic!("quote mismatch");
+                }
+                // LP fees are owed to positions rather than kept in reserves
+                (fee, protocol_fee, amount_in - fee)
+            }
+            _ => {
+                let fee = amount_in - Self::mul_div(env, amount_in, 10000 - config.fee_bps as i128, 10000);
+                let protocol_fee_bps: u32 = env.storage().instance().get(&DataKey::ProtocolFeeBps).unwrap_or(0);
+                let protocol_fee = Self::mul_div(env, fee, protocol_fee_bps as i128, 10000);
+                (fee, protocol_fee, amount_in - protocol_fee)
+            }
+        };
+        
+        Self::record_stats(env, pool_id, config, token_in, amount_in, fee - protocol_fee);
+        
+        let mut fees = Self::get_pool_fees(env.clone(), pool_id.clone());
+        if *token_in == config.token_a {
+            config.reserve_a += retained;
+            config.reserve_b -= amount_out;
+            fees.total_fees_a += fee;
+            fees.protocol_fees_a += protocol_fee;
+            fees.uncollected_a += protocol_fee;
+        } else {
+            config.reserve_b += retained;
+            config.reserve_a -= amount_out;
+            fees.total_fees_b += fee;
+            fees.protocol_fees_b += protocol_fee;
+            fees.uncollected_b += protocol_fee;
+        }
+        
+        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), config);
+        env.storage().persistent().set(&DataKey::PoolFees(pool_id.clone()), &fees);
+    }
+    
+    // Helper: add swap volume and LP fees (both in `token_in`) to the current hour's bucket
+    fn record_stats(env: &Env, pool_id: &BytesN<32>, config: &PoolConfig, token_in: &Address, volume: i128, fee: i128) {
+        let hour = env.ledger().timestamp() / STATS_BUCKET_SECONDS;
+        let key = DataKey::StatsBucket(pool_id.clone(), (hour % STATS_BUCKETS as u64) as u32);
+        
+        let mut bucket: StatsBucket = env.storage().persistent().get(&key)
+            .filter(|b: &StatsBucket| b.hour == hour)
+            .unwrap_or(StatsBucket { hour, volume_a: 0, volume_b: 0, fees_a: 0, fees_b: 0 });
+        if *token_in == config.token_a {
+            bucket.volume_a += volume;
+            bucket.fees_a += fee;
+        } else {
+            bucket.volume_b += volume;
+            bucket.fees_b += fee;
+        }
+        env.storage().persistent().set(&key, &bucket);
+    }
+    
+    // Helper: accumulators advanced to `now` at the pool's current spot prices
+    fn accumulate(env: &Env, pool_id: &BytesN<32>, state: &OracleState, config: &PoolConfig, now: u64) -> Observation {
+        let mut obs = Observation {
+            timestamp: now,
+            price_a_cumulative: state.price_a_cumulative,
+            price_b_cumulative: state.price_b_cumulative,
+        };
+        if now > state.last_update && Self::is_priced(config) {
+            let elapsed = (now - state.last_update) as i128;
+            obs.price_a_cumulative += Self::spot_price(env, pool_id, config, &config.token_a) * elapsed;
+            obs.price_b_cumulative += Self::spot_price(env, pool_id, config, &config.token_b) * elapsed;
+        }
+        obs
+    }
+    
+    // Helper: fold the pre-change reserves into the oracle; call before
+    // every reserve update. Stores an observation at most every OBSERVATION_INTERVAL.
+    fn update_oracle(env: &Env, pool_id: &BytesN<32>, config: &PoolConfig) {
+        let now = env.ledger().timestamp();
+        let stored: Option<OracleState> = env.storage().persistent().get(&DataKey::Oracle(pool_id.clone()));
+        
+        let mut state = match stored {
+            Some(state) => state,
+            None => {
+                let state = OracleState {
+                    price_a_cumulative: 0,
+                    price_b_cumulative: 0,
+                    last_update: now,
+                    index: 0,
+                    count: 1,
+                };
+                let obs = Self::accumulate(env, pool_id, &state, config, now);
+                env.storage().persistent().set(&DataKey::Observation(pool_id.clone(), 0), &obs);
+                env.storage().persistent().set(&DataKey::Oracle(pool_id.clone()), &state);
+                return;
+            }
+        };
+        
+        if now == state.last_update {
+            return;
+        }
+        
+        let obs = Self::accumulate(env, pool_id, &state, config, now);
+        state.price_a_cumulative = obs.price_a_cumulative;
+        state.price_b_cumulative = obs.price_b_cumulative;
+        state.last_update = now;
+        
+        let latest: Observation = env.storage().persistent()
+            .get(&DataKey::Observation(pool_id.clone(), state.index))
+            .unwrap();
+        if now - latest.timestamp >= OBSERVATION_INTERVAL {
+            state.index = (state.index + 1) % OBSERVATION_CAPACITY;
+            if state.count < OBSERVATION_CAPACITY {
+                state.count += 1;
+            }
+            env.storage().persistent().set(&DataKey::Observation(pool_id.clone(), state.index), &obs);
+        }
+        
+        env.storage().persistent().set(&DataKey::Oracle(pool_id.clone()), &state);
+    }
+    
+    // Helper: whether a pool has a defined price (range pools always do)
+    fn is_priced(config: &PoolConfig) -> bool {
+        match config.kind {
+            PoolKind::Concentrated(_) => true,
+            _ => config.reserve_a > 0 && config.reserve_b > 0,
+        }
+    }
+    
+    // Helper: widen a non-negative amount for U256 math
+    fn u256(env: &Env, value: i128) -> U256 {
+        U256::from_u128(env, value as u128)
+    }
+    
+    // Helper: ceil(n / d) in U256
+    fn div_up(env: &Env, n: &U256, d: &U256) -> U256 {
+        n.add(d).sub(&U256::from_u32(env, 1)).div(d)
+    }
+    
+    // Helper: load a range pool's state
+    fn range_state(env: &Env, pool_id: &BytesN<32>) -> RangeState {
+        env.storage().persistent()
+            .get(&DataKey::RangePool(pool_id.clone()))
+            .expect("not a range pool")
+    }
+    
+    // Helper: load a position and check it belongs to `owner`
+    fn owned_position(env: &Env, owner: &Address, position_id: u64) -> RangePosition {
+        let position: RangePosition = env.storage().persistent()
+            .get(&DataKey::Position(position_id))
+            .expect("position not found");
+        if position.owner != *owner {
+            panic!("not position owner");
+        }
+        position
+    }
+    
+    // Helper: drop a position ID from its owner's list
+    fn remove_owned_position(env: &Env, owner: &Address, position_id: u64) {
+        let key = DataKey::OwnerPositions(owner.clone());
+        let mut owned: Vec<u64> = env.storage().persistent().get(&key).unwrap_or(Vec::new(env));
+        if let Some(i) = owned.first_index_of(position_id) {
+            owned.remove(i);
+        }
+        env.storage().persistent().set(&key, &owned);
+    }
+    
+    // Helper: sqrt(1.0001^tick) in Q64.64, by binary exponentiation
+    fn sqrt_price_at_tick(env: &Env, tick: i32) -> U256 {
+        let mut ratio = U256::from_u32(env, 1).shl(64);
+        let mut base = U256::from_u128(env, SQRT_TICK_RATIO_Q64);
+        let mut n = tick.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                ratio = ratio.mul(&base).shr(64);
+            }
+            n >>= 1;
+            if n > 0 {
+                base = base.mul(&base).shr(64);
+            }
+        }
+        if tick < 0 {
+            ratio = U256::from_u32(env, 1).shl(128).div(&ratio);
+        }
+        ratio
+    }
+    
+    // Helper: greatest tick in [lo, hi] whose sqrt price is <= `sqrt_price`
+    fn tick_at_sqrt_price(env: &Env, sqrt_price: &U256, lo: i32, hi: i32) -> i32 {
+        let (mut lo, mut hi) = (lo, hi);
+        while lo < hi {
+            let mid = lo + (hi - lo + 1) / 2;
+            if Self::sqrt_price_at_tick(env, mid) <= *sqrt_price {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        lo
+    }
+    
+    // Helper: token_a owed for `liquidity` between sqrt prices lo < hi
+    fn amount_a_delta(env: &Env, lo: &U256, hi: &U256, liquidity: i128, round_up: bool) -> i128 {
+        let num = Self::u256(env, liquidity).shl(64).mul(&hi.sub(lo));
+        if round_up {
+            Self::to_i128(Self::div_up(env, &Self::div_up(env, &num, hi), lo))
+        } else {
+            Self::to_i128(num.div(hi).div(lo))
+        }
+    }
+    
+    // Helper: token_b owed for `liquidity` between sqrt prices lo < hi
+    fn amount_b_delta(env: &Env, lo: &U256, hi: &U256, liquidity: i128, round_up: bool) -> i128 {
+        let num = Self::u256(env, liquidity).mul(&hi.sub(lo));
+        if round_up {
+            Self::to_i128(Self::div_up(env, &num, &U256::from_u32(env, 1).shl(64)))
+        } else {
+            Self::to_i128(num.shr(64))
+        }
+    }
+    
+    // Helper: largest liquidity the given amounts can back on [tick_lower, tick_upper)
+    fn liquidity_for_amounts(
+        env: &Env,
+        state: &RangeState,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount_a: i128,
+        amount_b: i128,
+    ) -> i128 {
+        let lower = Self::sqrt_price_at_tick(env, tick_lower);
+        let upper = Self::sqrt_price_at_tick(env, tick_upper);
+        let from_a = |lo: &U256, hi: &U256| {
+            Self::to_i128(Self::u256(env, amount_a).mul(&lo.mul(hi).shr(64)).div(&hi.sub(lo)))
+        };
+        let from_b = |lo: &U256, hi: &U256| {
+            Self::to_i128(Self::u256(env, amount_b).shl(64).div(&hi.sub(lo)))
+        };
+        
+        if state.tick < tick_lower {
+            from_a(&lower, &upper)
+        } else if state.tick < tick_upper {
+            if state.sqrt_price == lower {
+                return from_a(&lower, &upper);
+            }
+            from_a(&state.sqrt_price, &upper).min(from_b(&lower, &state.sqrt_price))
+        } else {
+            from_b(&lower, &upper)
+        }
+    }
+    
+    // Helper: token amounts backing `liquidity` on [tick_lower, tick_upper) at the current price
+    fn amounts_for_liquidity(
+        env: &Env,
+        state: &RangeState,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: i128,
+        round_up: bool,
+    ) -> (i128, i128) {
+        let lower = Self::sqrt_price_at_tick(env, tick_lower);
+        let upper = Self::sqrt_price_at_tick(env, tick_upper);
+        
+        if state.tick < tick_lower {
+            (Self::amount_a_delta(env, &lower, &upper, liquidity, round_up), 0)
+        } else if state.tick < tick_upper {
+            (
+                Self::amount_a_delta(env, &state.sqrt_price, &upper, liquidity, round_up),
+                Self::amount_b_delta(env, &lower, &state.sqrt_price, liquidity, round_up),
+            )
+        } else {
+            (0, Self::amount_b_delta(env, &lower, &upper, liquidity, round_up))
+        }
+    }
+    
+    // Helper: fee growth accrued inside [tick_lower, tick_upper)
+    fn fee_growth_inside(env: &Env, pool_id: &BytesN<32>, state: &RangeState, tick_lower: i32, tick_upper: i32) -> (i128, i128) {
+        let lower = Self::range_tick(env, pool_id, tick_lower);
+        let upper = Self::range_tick(env, pool_id, tick_upper);
+        
+        let (below_a, below_b) = if state.tick >= tick_lower {
+            (lower.fee_growth_outside_a, lower.fee_growth_outside_b)
+        } else {
+            (state.fee_growth_a - lower.fee_growth_outside_a, state.fee_growth_b - lower.fee_growth_outside_b)
+        };
+        let (above_a, above_b) = if state.tick < tick_upper {
+            (upper.fee_growth_outside_a, upper.fee_growth_outside_b)
+        } else {
+            (state.fee_growth_a - upper.fee_growth_outside_a, state.fee_growth_b - upper.fee_growth_outside_b)
+        };
+        
+        (state.fee_growth_a - below_a - above_a, state.fee_growth_b - below_b - above_b)
+    }
+    
+    // Helper: load a tick, empty if uninitialized
+    fn range_tick(env: &Env, pool_id: &BytesN<32>, tick: i32) -> RangeTick {
+        env.storage().persistent()
+            .get(&DataKey::RangeTick(pool_id.clone(), tick))
+            .unwrap_or(RangeTick {
+                liquidity_gross: 0,
+                liquidity_net: 0,
+                fee_growth_outside_a: 0,
+                fee_growth_outside_b: 0,
+            })
+    }
+    
+    // Helper: apply a liquidity change to a tick, initializing it on first
+    // use; returns whether the tick is now unreferenced
+    fn update_tick(env: &Env, pool_id: &BytesN<32>, state: &RangeState, tick: i32, delta: i128, upper: bool) -> bool {
+        let mut info = Self::range_tick(env, pool_id, tick);
+        
+        if info.liquidity_gross == 0 {
+            // By convention all growth so far happened below the current tick
+            if tick <= state.tick {
+                info.fee_growth_outside_a = state.fee_growth_a;
+                info.fee_growth_outside_b = state.fee_growth_b;
+            }
+            let key = DataKey::RangeTicks(pool_id.clone());
+            let mut ticks: Vec<i32> = env.storage().persistent().get(&key).unwrap_or(Vec::new(env));
+            if let Err(i) = ticks.binary_search(tick) {
+                ticks.insert(i, tick);
+            }
+            env.storage().persistent().set(&key, &ticks);
+        }
+        
+        info.liquidity_gross += delta;
+        info.liquidity_net += if upper { -delta } else { delta };
+        env.storage().persistent().set(&DataKey::RangeTick(pool_id.clone(), tick), &info);
+        
+        info.liquidity_gross == 0
+    }
+    
+    // Helper: nearest initialized tick in the swap direction, at or below
+    // `tick` when selling token_a and above it otherwise (binary search)
+    fn next_initialized_tick(ticks: &Vec<i32>, tick: i32, zero_for_one: bool) -> Option<i32> {
+        match (ticks.binary_search(tick), zero_for_one) {
+            (Ok(i), true) => ticks.get(i),
+            (Err(i), true) => if i > 0 { ticks.get(i - 1) } else { None },
+            (Ok(i), false) => ticks.get(i + 1),
+            (Err(i), false) => ticks.get(i),
+        }
+    }
+    
+    // Helper: forget an unreferenced tick
+    fn clear_tick(env: &Env, pool_id: &BytesN<32>, tick: i32) {
+        env.storage().persistent().remove(&DataKey::RangeTick(pool_id.clone(), tick));
+        let key = DataKey::RangeTicks(pool_id.clone());
+        let mut ticks: Vec<i32> = env.storage().persistent().get(&key).unwrap();
+        if let Ok(i) = ticks.binary_search(tick) {
+            ticks.remove(i);
+        }
+        env.storage().persistent().set(&key, &ticks);
+    }
+    
+    // Helper: settle a position's fees and change its liquidity by `delta`
+    fn modify_position(
+        env: &Env,
+        pool_id: &BytesN<32>,
+        state: &mut RangeState,
+        position: &mut RangePosition,
+        delta: i128,
+    ) {
+        let (lower, upper) = (position.tick_lower, position.tick_upper);
+        let (mut clear_lower, mut clear_upper) = (false, false);
+        if delta != 0 {
+            clear_lower = Self::update_tick(env, pool_id, state, lower, delta, false);
+            clear_upper = Self::update_tick(env, pool_id, state, upper, delta, true);
+        }
+        
+        let (inside_a, inside_b) = Self::fee_growth_inside(env, pool_id, state, lower, upper);
+        position.fees_owed_a += Self::mul_div(env, position.liquidity, inside_a - position.fee_growth_inside_a, FEE_GROWTH_PRECISION);
+        position.fees_owed_b += Self::mul_div(env, position.liquidity, inside_b - position.fee_growth_inside_b, FEE_GROWTH_PRECISION);
+        position.fee_growth_inside_a = inside_a;
+        position.fee_growth_inside_b = inside_b;
+        position.liquidity += delta;
+        
+        if state.tick >= lower && state.tick < upper {
+            state.liquidity += delta;
+        }
+        
+        if clear_lower {
+            Self::clear_tick(env, pool_id, lower);
+        }
+        if clear_upper {
+            Self::clear_tick(env, pool_id, upper);
+        }
+    }
+    
+    // Helper: exact-input swap through a range pool, crossing initialized
+    // ticks as the price moves. Returns (amount_out, fee, protocol_fee), or
+    // None if liquidity runs out. State is only written when `execute` is set.
+    fn range_swap(
+        env: &Env,
+        pool_id: &BytesN<32>,
+        config: &PoolConfig,
+        token_in: &Address,
+        amount_in: i128,
+        execute: bool,
+    ) -> Option<(i128, i128, i128)> {
+        let zero_for_one = *token_in == config.token_a;
+        let mut state = Self::range_state(env, pool_id);
+        let ticks: Vec<i32> = env.storage().persistent()
+            .get(&DataKey::RangeTicks(pool_id.clone()))
+            .unwrap_or(Vec::new(env));
+        let fee_factor = 10000 - config.fee_bps as i128;
+        let protocol_fee_bps: u32 = env.storage().instance().get(&DataKey::ProtocolFeeBps).unwrap_or(0);
+        
+        let mut remaining = amount_in;
+        let mut amount_out = 0;
+        let mut fee_total = 0;
+        let mut protocol_total = 0;
+        
+        while remaining > 0 {
+            let next = Self::next_initialized_tick(&ticks, state.tick, zero_for_one);
+            let target_tick = next.unwrap_or(if zero_for_one { MIN_TICK } else { MAX_TICK });
+            let target = Self::sqrt_price_at_tick(env, target_tick);
+            let liquidity = state.liquidity;
+            let less_fee = Self::mul_div(env, remaining, fee_factor, 10000);
+            
+            let needed = if liquidity == 0 {
+                0
+            } else if zero_for_one {
+                Self::amount_a_delta(env, &target, &state.sqrt_price, liquidity, true)
+            } else {
+                Self::amount_b_delta(env, &state.sqrt_price, &target, liquidity, true)
+            };
+            
+            let (step_in, next_sqrt) = if less_fee >= needed {
+                (needed, target.clone())
+            } else if zero_for_one {
+                // sqrt' = L * sqrt / (L + amount * sqrt), rounded up
+                let l = Self::u256(env, liquidity).shl(64);
+                let den = l.add(&Self::u256(env, less_fee).mul(&state.sqrt_price));
+                (less_fee, Self::div_up(env, &l.mul(&state.sqrt_price), &den))
+            } else {
+                // sqrt' = sqrt + amount / L, rounded down
+                let step = Self::u256(env, less_fee).shl(64).div(&Self::u256(env, liquidity));
+                (less_fee, state.sqrt_price.add(&step))
+            };
+            
+            let step_out = if liquidity == 0 {
+                0
+            } else if zero_for_one {
+                Self::amount_b_delta(env, &next_sqrt, &state.sqrt_price, liquidity, false)
+            } else {
+                Self::amount_a_delta(env, &state.sqrt_price, &next_sqrt, liquidity, false)
+            };
+            
+            let reached = next_sqrt == target;
+            let fee = if reached {
+                Self::mul_div_ceil(env, step_in, config.fee_bps as i128, fee_factor).min(remaining - step_in)
+            } else {
+                remaining - step_in
+            };
+            let protocol_fee = Self::mul_div(env, fee, protocol_fee_bps as i128, 10000);
+            
+            remaining -= step_in + fee;
+            amount_out += step_out;
+            fee_total += fee;
+            protocol_total += protocol_fee;
+            
+            if liquidity > 0 {
+                let growth = Self::mul_div(env, fee - protocol_fee, FEE_GROWTH_PRECISION, liquidity);
+                if zero_for_one {
+                    state.fee_growth_a += growth;
+                } else {
+                    state.fee_growth_b += growth;
+                }
+            }
+            
+            let previous_tick = state.tick;
+            state.sqrt_price = next_sqrt;
+            if reached {
+                match next {
+                    Some(t) => {
+                        let mut info = Self::range_tick(env, pool_id, t);
+                        info.fee_growth_outside_a = state.fee_growth_a - info.fee_growth_outside_a;
+                        info.fee_growth_outside_b = state.fee_growth_b - info.fee_growth_outside_b;
+                        if execute {
+                            env.storage().persistent().set(&DataKey::RangeTick(pool_id.clone(), t), &info);
+                        }
+                        if zero_for_one {
+                            state.liquidity -= info.liquidity_net;
+                        } else {
+                            state.liquidity += info.liquidity_net;
+                        }
+                    }
+                    None => {
+                        if remaining > 0 {
+                            return None;
+                        }
+                    }
+                }
+                state.tick = if zero_for_one { target_tick - 1 } else { target_tick };
+            } else if zero_for_one {
+                state.tick = Self::tick_at_sqrt_price(env, &state.sqrt_price, target_tick, previous_tick);
+            } else {
+                state.tick = Self::tick_at_sqrt_price(env, &state.sqrt_price, previous_tick, target_tick);
+            }
+        }
+        
+        if execute {
+            env.storage().persistent().set(&DataKey::RangePool(pool_id.clone()), &state);
+        }
+        
+        Some((amount_out, fee_total, protocol_total))
+    }
+    
+    // Helper: reject token inflows while a flash loan is outstanding
+    fn check_unlocked(env: &Env) {
+        if env.storage().instance().has(&DataKey::FlashLoanLock) {
+            panic!("flash loan in progress");
+        }
+    }
+    
+    // Helper: reject transactions submitted after their deadline
+    fn check_deadline(env: &Env, deadline: u64) {
+        if env.ledger().timestamp() > deadline {
+            panic!("deadline passed");
+        }
+    }
+    
+    // Helper: pools registered for a pair, loaded with their config
+    fn pair_configs(env: &Env, token_in: &Address, token_out: &Address) -> Vec<(BytesN<32>, PoolConfig)> {
+        if token_in == token_out {
+            panic!("identical tokens");
+        }
+        
+        let pools = Self::priced_pools(env, token_in, token_out);
+        if pools.is_empty() {
+            panic!("no pool for pair");
+        }
+        
+        pools
+    }
+    
+    // Helper: all pools of a pair that currently have a price
+    fn priced_pools(env: &Env, token_in: &Address, token_out: &Address) -> Vec<(BytesN<32>, PoolConfig)> {
+        let (a, b) = Self::sort_tokens(token_in.clone(), token_out.clone());
+        let ids: Vec<BytesN<32>> = env.storage().persistent()
+            .get(&DataKey::PairPools(a, b))
+            .unwrap_or(Vec::new(env));
+        
+        let mut pools = Vec::new(env);
+        for id in ids.iter() {
+            let config: PoolConfig = env.storage().persistent()
+                .get(&DataKey::Pool(id.clone()))
+                .unwrap();
+            if Self::is_priced(&config) {
+                pools.push_back((id, config));
+            }
+        }
+        
+        pools
+    }
+    
+    // Helper: the pair's priced pool holding the most `token`, earliest
+    // registered on ties; pools without any `token` are skipped
+    fn deepest_pool(env: &Env, token: &Address, other: &Address) -> Option<(BytesN<32>, PoolConfig)> {
+        let mut best: Option<(BytesN<32>, PoolConfig)> = None;
+        let mut best_reserve = 0;
+        for (id, pool) in Self::priced_pools(env, token, other).iter() {
+            let (reserve, _) = Self::reserves_for(&pool, token);
+            if reserve > best_reserve {
+                best_reserve = reserve;
+                best = Some((id, pool));
+            }
+        }
+        best
+    }
+    
+    // Helper: price of `token` in `config`'s token_b (PRICE_PRECISION), via
+    // the pool itself or the deepest pool pairing it with either side;
+    // None when no such pool exists
+    fn price_in_token_b(env: &Env, config: &PoolConfig, price_a: i128, token: &Address) -> Option<i128> {
+        if *token == config.token_b {
+            return Some(PRICE_PRECISION);
+        }
+        if *token == config.token_a {
+            return Some(price_a);
+        }
+        if let Some((id, pool)) = Self::deepest_pool(env, token, &config.token_b) {
+            return Some(Self::spot_price(env, &id, &pool, token));
+        }
+        if let Some((id, pool)) = Self::deepest_pool(env, token, &config.token_a) {
+            return Some(Self::mul_div(env, Self::spot_price(env, &id, &pool, token), price_a, PRICE_PRECISION));
+        }
+        None
+    }
+    
+    // Helper: forward route; picks the highest-output pool at each hop. Hops are
+    // quoted against current reserves, so each pool may serve only one hop.
+    fn route_out(env: &Env, path: &Vec<Address>, amount_in: i128) -> (Vec<BytesN<32>>, Vec<i128>) {
+        if path.len() < 2 {
+            panic!("invalid path");
+        }
+        
+        let mut pool_ids = Vec::new(env);
+        let mut amounts = Vec::new(env);
+        amounts.push_back(amount_in);
+        
+        let mut current = amount_in;
+        for i in 0..path.len() - 1 {
+            let token_in = path.get(i).unwrap();
+            let token_out = path.get(i + 1).unwrap();
+            
+            let mut best: Option<(BytesN<32>, i128)> = None;
+            let mut reused = false;
+            for (id, config) in Self::pair_configs(env, &token_in, &token_out).iter() {
+                if pool_ids.contains(&id) {
+                    reused = true;
+                    continue;
+                }
+                let out = match config.kind {
+                    PoolKind::Concentrated(_) => match Self::range_swap(env, &id, &config, &token_in, current, false) {
+                        Some((out, _, _)) => out,
+                        None => continue,
+                    },
+                    _ => Self::quote_out(env, &id, &config, &token_in, current),
+                };
+                if best.as_ref().is_none_or(|(_, b)| out > *b) {
+                    best = Some((id, out));
+                }
+            }
+            
+            let (id, out) = match best {
+                Some(best) => best,
+                None if reused => panic!("pool reused in path"),
+                None => panic!("insufficient liquidity"),
+            };
+            pool_ids.push_back(id);
+            amounts.push_back(out);
+            current = out;
+        }
+        
+        (pool_ids, amounts)
+    }
+    
+    // Helper: reverse route; picks the cheapest-input pool at each hop, using
+    // each pool at most once like `route_out`
+    fn route_in(env: &Env, path: &Vec<Address>, amount_out: i128) -> (Vec<BytesN<32>>, Vec<i128>) {
+        if path.len() < 2 {
+            panic!("invalid path");
+        }
+        
+        let mut pool_ids = Vec::new(env);
+        let mut amounts = Vec::new(env);
+        amounts.push_front(amount_out);
+        
+        let mut current = amount_out;
+        let mut i = path.len() - 1;
+        while i > 0 {
+            let token_in = path.get(i - 1).unwrap();
+            let token_out = path.get(i).unwrap();
+            
+            let mut best: Option<(BytesN<32>, i128)> = None;
+            let mut reused = false;
+            for (id, config) in Self::pair_configs(env, &token_in, &token_out).iter() {
+                if pool_ids.contains(&id) {
+                    reused = true;
+                    continue;
+                }
+                if let PoolKind::Concentrated(_) = config.kind {
+                    continue;
+                }
+                let (_, reserve_out) = Self::reserves_for(&config, &token_in);
+                if current >= reserve_out {
+                    continue;
+                }
+                let needed = Self::quote_in(env, &config, &token_in, current);
+                if best.as_ref().is_none_or(|(_, b)| needed < *b) {
+                    best = Some((id, needed));
+                }
+            }
+            
+            let (id, needed) = match best {
+                Some(best) => best,
+                None if reused => panic!("pool reused in path"),
+                None => panic!("insufficient liquidity"),
+            };
+            pool_ids.push_front(id);
+            amounts.push_front(needed);
+            current = needed;
+            i -= 1;
+        }
+        
+        (pool_ids, amounts)
+    }
+    
+    // Helper: settle a quoted route; intermediate tokens never leave the contract
+    fn execute_route(
+        env: &Env,
+        user: &Address,
+        path: &Vec<Address>,
+        pool_ids: &Vec<BytesN<32>>,
+        amounts: &Vec<i128>,
+    ) {
+        let last = path.len() - 1;
+        
+        token::Client::new(env, &path.get(0).unwrap())
+            .transfer(user, &env.current_contract_address(), &amounts.get(0).unwrap());
+        
+        for i in 0..last {
+            let pool_id = pool_ids.get(i).unwrap();
+            let mut config: PoolConfig = env.storage().persistent()
+                .get(&DataKey::Pool(pool_id.clone()))
+                .unwrap();
+            let amount_in = amounts.get(i).unwrap();
+            let amount_out = amounts.get(i + 1).unwrap();
+            
+            Self::apply_hop(env, &pool_id, &mut config, &path.get(i).unwrap(), amount_in, amount_out);
+            
+            env.events().publish(
+                (symbol_short!("swap"), user.clone(), pool_id),
+                (amount_in, amount_out),
+            );
+        }
+        
+        token::Client::new(env, &path.get(last).unwrap())
+            .transfer(&env.current_contract_address(), user, &amounts.get(last).unwrap());
+    }
+    
+    // Helper: load a staking pool and accrue emissions up to now. Emission
+    // pauses while nothing is staked and stops once the budget is spent.
+    fn load_staking_pool(env: &Env, pool_id: &BytesN<32>) -> StakingPool {
+        let mut staking_pool: StakingPool = env.storage().persistent()
+            .get(&DataKey::StakingPool(pool_id.clone()))
+            .expect("staking pool not found");
+        
+        let now = env.ledger().timestamp();
+        if now > staking_pool.last_update && staking_pool.total_staked > 0 {
+            let elapsed = (now - staking_pool.last_update) as i128;
+            let emitted = staking_pool.reward_rate.saturating_mul(elapsed).min(staking_pool.reward_budget);
+            staking_pool.reward_per_share += Self::mul_div(env, emitted, REWARD_PRECISION, staking_pool.total_staked);
+            staking_pool.reward_budget -= emitted;
+        }
+        staking_pool.last_update = now;
+        
+        staking_pool
+    }
+    
+    // Helper: move rewards accrued since the last settlement into `unclaimed`
+    // and fold redistributed penalty shares into the stake
+    fn settle_stake(env: &Env, staking_pool: &mut StakingPool, stake: &mut StakingPosition) {
+        let accrued = Self::mul_div(env, stake.amount, staking_pool.reward_per_share, REWARD_PRECISION);
+        stake.unclaimed += accrued - stake.reward_debt;
+        
+        let credited = Self::mul_div(env, stake.amount, staking_pool.penalty_per_share, REWARD_PRECISION) - stake.penalty_debt;
+        stake.amount += credited;
+        staking_pool.total_staked += credited;
+        
+        Self::checkpoint_stake(env, staking_pool, stake);
+    }
+    
+    // Helper: reset accumulator debts after a stake's amount changes
+    fn checkpoint_stake(env: &Env, staking_pool: &StakingPool, stake: &mut StakingPosition) {
+        stake.reward_debt = Self::mul_div(env, stake.amount, staking_pool.reward_per_share, REWARD_PRECISION);
+        stake.penalty_debt = Self::mul_div(env, stake.amount, staking_pool.penalty_per_share, REWARD_PRECISION);
+    }
+    
+    // Helper: penalty for withdrawing `amount` now; None if locked with no exit allowed
+    fn early_exit_penalty(env: &Env, staking_pool: &StakingPool, stake: &StakingPosition, amount: i128) -> Option<i128> {
+        if env.ledger().timestamp() >= stake.staked_at + staking_pool.lock_period {
+            return Some(0);
+        }
+        if staking_pool.early_exit_penalty_bps == 0 {
+            return None;
+        }
+        Some(Self::mul_div(env, amount, staking_pool.early_exit_penalty_bps as i128, 10000))
+    }
+    
+    // Helper: burn or redistribute forfeited shares; burns when nobody is left to receive them
+    fn apply_exit_penalty(env: &Env, pool_id: &BytesN<32>, staking_pool: &mut StakingPool, penalty: i128) {
+        if penalty == 0 {
+            return;
+        }
+        
+        if staking_pool.penalty_mode == PenaltyMode::Redistribute && staking_pool.total_staked > 0 {
+            staking_pool.penalty_per_share += Self::mul_div(env, penalty, REWARD_PRECISION, staking_pool.total_staked);
+            return;
+        }
+        
+        let mut config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        token::Client::new(env, &config.share_token).burn(&env.current_contract_address(), &penalty);
+        config.total_shares -= penalty;
+        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
+    }
+    
+    // Helper: release escrowed LP shares
+    fn transfer_staked_shares(env: &Env, pool_id: &BytesN<32>, to: &Address, amount: i128) {
+        if amount == 0 {
+            return;
+        }
+        
+        let config: PoolConfig = env.storage().persistent()
+            .get(&DataKey::Pool(pool_id.clone()))
+            .expect("pool not found");
+        token::Client::new(env, &config.share_token).transfer(&env.current_contract_address(), to, &amount);
+    }
+    
+    // Helper: curve of a pool kind, dropping its parameters
+    fn curve(kind: &PoolKind) -> PoolCurve {
+        match kind {
+            PoolKind::ConstantProduct => PoolCurve::ConstantProduct,
+            PoolKind::StableSwap(_) => PoolCurve::StableSwap,
+            PoolKind::Concentrated(_) => PoolCurve::Concentrated,
+        }
+    }
+    
+    // Helper: the pair's pool of `curve` currently charging `fee_bps`
+    fn find_pair_pool(
+        env: &Env,
+        token_a: &Address,
+        token_b: &Address,
+        fee_bps: u32,
+        curve: PoolCurve,
+    ) -> Option<BytesN<32>> {
+        let ids: Vec<BytesN<32>> = env.storage().persistent()
+            .get(&DataKey::PairPools(token_a.clone(), token_b.clone()))
+            .unwrap_or(Vec::new(env));
+        for id in ids.iter() {
+            let config: PoolConfig = env.storage().persistent().get(&DataKey::Pool(id.clone())).unwrap();
+            if config.fee_bps == fee_bps && Self::curve(&config.kind) == curve {
+                return Some(id);
+            }
+        }
+        None
+    }
+    
+    // Helper: validate, deploy the share token for and register a new pool
+    fn register_pool(env: &Env, token_a: Address, token_b: Address, fee_bps: u32, kind: PoolKind) -> BytesN<32> {
+        if token_a == token_b {
+            panic!("identical tokens");
+        }
+        let fee_tiers: Vec<u32> = env.storage().instance().get(&DataKey::FeeTiers).unwrap_or(Vec::new(env));
+        if !fee_tiers.contains(fee_bps) {
+            panic!("fee tier not enabled");
+        }
+        
+        let (token_a, token_b) = Self::sort_tokens(token_a, token_b);
+        
+        if Self::find_pair_pool(env, &token_a, &token_b, fee_bps, Self::curve(&kind)).is_some() {
+            panic!("pool already exists");
+        }
+        
+        // Generate pool ID from canonical token pair, fee tier and curve
+        let mut pool_id = match kind {
+            PoolKind::ConstantProduct => Self::pool_id(env, &token_a, &token_b, fee_bps),
+            PoolKind::StableSwap(_) => Self::stable_pool_id(env, &token_a, &token_b, fee_bps),
+            PoolKind::Concentrated(_) => Self::range_pool_id(env, &token_a, &token_b, fee_bps),
+        };
+        // A pool moved off this tier by set_fee_bps still holds the derived ID
+        while env.storage().persistent().has(&DataKey::Pool(pool_id.clone())) {
+            pool_id = env.crypto().sha256(&pool_id.to_xdr(env)).into();
+        }
+        
+        // Deploy the pool's share token, salted by pool ID
+        let share_token_wasm: BytesN<32> = env.storage().instance().get(&DataKey::ShareTokenWasm).unwrap();
+        let share_token = env.deployer()
+            .with_current_contract(pool_id.clone())
+            .deploy(share_token_wasm);
+        ShareTokenClient::new(env, &share_token).initialize(
+            &env.current_contract_address(),
+            &7u32,
+            &String::from_str(env, "CCA LP Share"),
+            &String::from_str(env, "CCA-LP"),
+        );
+        
+        let config = PoolConfig {
+            token_a: token_a.clone(),
+            token_b: token_b.clone(),
+            reserve_a: 0,
+            reserve_b: 0,
+            total_shares: 0,
+            fee_bps,
+            share_token,
+            kind,
+        };
+        
+        env.storage().persistent().set(&DataKey::Pool(pool_id.clone()), &config);
+        
+        // Register pool
+        let total: u32 = env.storage().instance().get(&DataKey::TotalPools).unwrap_or(0);
+        env.storage().persistent().set(&DataKey::PoolIndex(total), &pool_id);
+        env.storage().instance().set(&DataKey::TotalPools, &(total + 1));
+        
+        let pair_key = DataKey::PairPools(token_a.clone(), token_b.clone());
+        let mut pair_pools: Vec<BytesN<32>> = env.storage().persistent().get(&pair_key).unwrap_or(Vec::new(env));
+        pair_pools.push_back(pool_id.clone());
+        env.storage().persistent().set(&pair_key, &pair_pools);
+        
+        env.events().publish(
+            (symbol_short!("pool_new"), token_a, token_b),
+            fee_bps,
+        );
+        
+        pool_id
+    }
+    
+    // Helper: StableSwap pool ID, kept distinct from the constant-product pool of the same tier
+    fn stable_pool_id(env: &Env, token_a: &Address, token_b: &Address, fee_bps: u32) -> BytesN<32> {
+        env.crypto().sha256(&(token_a.clone(), token_b.clone(), fee_bps, symbol_short!("stable")).to_xdr(env)).into()
+    }
+    
+    // Helper: range pool ID, distinct from the other curves of the same tier
+    fn range_pool_id(env: &Env, token_a: &Address, token_b: &Address, fee_bps: u32) -> BytesN<32> {
+        env.crypto().sha256(&(token_a.clone(), token_b.clone(), fee_bps, symbol_short!("range")).to_xdr(env)).into()
+    }
+    
+    // Helper: canonical token ordering
+    fn sort_tokens(token_a: Address, token_b: Address) -> (Address, Address) {
+        if token_a < token_b { (token_a, token_b) } else { (token_b, token_a) }
+    }
+    
+    // Helper: deterministic pool ID for a canonical pair and fee tier
+    fn pool_id(env: &Env, token_a: &Address, token_b: &Address, fee_bps: u32) -> BytesN<32> {
+        env.crypto().sha256(&(token_a.clone(), token_b.clone(), fee_bps).to_xdr(env)).into()
+    }
+    
+    // Helper: integer square root
+    fn sqrt(env: &Env, n: U256) -> U256 {
+        let zero = U256::from_u32(env, 0);
+        if n == zero { return zero; }
+        let mut x = n.clone();
+        let mut y = n.add(&U256::from_u32(env, 1)).shr(1);
+        while y < x {
+            x = y;
+            y = x.add(&n.div(&x)).shr(1);
+        }
+        x
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::token::StellarAssetClient;
+
+    use lp_share_token::LPShareTokenClient;
+
+    // Built by build.rs; pools deploy their share token from this wasm
+    const SHARE_TOKEN_WASM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/lp_share_token.wasm"));
+
+    const UNIT: i128 = 10_000_000; // 7 decimals
+
+    struct Setup<'a> {
+        env: Env,
+        client: LiquidityPoolClient<'a>,
+        token_a: Address,
+        token_b: Address,
+    }
+
+    // Initialized contract and a canonically ordered pair of SAC tokens
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.budget().reset_unlimited();
+        
+        let contract_id = env.register_contract(None, LiquidityPool);
+        let client = LiquidityPoolClient::new(&env, &contract_id);
+        let admin = Address::generate(&env);
+        let wasm_hash = env.deployer().upload_contract_wasm(SHARE_TOKEN_WASM);
+        client.initialize(&admin, &wasm_hash);
+        
+        let (token_a, token_b) = LiquidityPool::sort_tokens(new_token(&env), new_token(&env));
+        Setup { env, client, token_a, token_b }
+    }
+
+    fn new_token(env: &Env) -> Address {
+        env.register_stellar_asset_contract_v2(Address::generate(env)).address()
+    }
+
+    fn mint(env: &Env, token: &Address, to: &Address, amount: i128) {
+        StellarAssetClient::new(env, token).mint(to, &amount);
+    }
+
+    fn balance(env: &Env, token: &Address, id: &Address) -> i128 {
+        token::Client::new(env, token).balance(id)
+    }
+
+    // Constant-product pool on the setup pair, seeded by a fresh LP
+    fn seeded_pool(s: &Setup, fee_bps: u32, amount_a: i128, amount_b: i128) -> (BytesN<32>, Address) {
+        let pool_id = s.client.create_pool(&s.token_a, &s.token_b, &fee_bps);
+        let lp = fund_pool(s, &pool_id, amount_a, amount_b);
+        (pool_id, lp)
+    }
+
+    // Deposit into an existing pool from a fresh LP
+    fn fund_pool(s: &Setup, pool_id: &BytesN<32>, amount_a: i128, amount_b: i128) -> Address {
+        let config = s.client.get_pool(pool_id);
+        let lp = Address::generate(&s.env);
+        mint(&s.env, &config.token_a, &lp, amount_a);
+        mint(&s.env, &config.token_b, &lp, amount_b);
+        s.client.add_liquidity(&lp, pool_id, &amount_a, &amount_b, &0, &0);
+        lp
+    }
+
+    fn pool(env: &Env, kind: PoolKind) -> PoolConfig {
+        PoolConfig {
+            token_a: Address::generate(env),
+            token_b: Address::generate(env),
+            reserve_a: 1_000_000 * UNIT,
+            reserve_b: 1_000_000 * UNIT,
+            total_shares: 1_000_000 * UNIT,
+            fee_bps: 5,
+            share_token: Address::generate(env),
+            kind,
+        }
+    }
+
+    fn stable(amp: u32) -> PoolKind {
+        PoolKind::StableSwap(StableParams {
+            initial_amp: amp,
+            future_amp: amp,
+            ramp_start: 0,
+            ramp_end: 0,
+        })
+    }
+
+    #[test]
+    fn test_stable_swap_slippage_below_constant_product() {
+        let env = Env::default();
+        let cp = pool(&env, PoolKind::ConstantProduct);
+        let ss = pool(&env, stable(100));
+        let id = BytesN::from_array(&env, &[0; 32]);
+        
+        let amount_in = 100_000 * UNIT; // 10% of reserves
+        let cp_out = LiquidityPool::quote_out(&env, &id, &cp, &cp.token_a, amount_in);
+        let ss_out = LiquidityPool::quote_out(&env, &id, &ss, &ss.token_a, amount_in);
+        
+        assert!(ss_out > cp_out);
+        // Constant product loses ~9% here; the stable curve stays within 0.2%
+        assert!(cp_out < amount_in * 92 / 100);
+        assert!(ss_out > amount_in * 998 / 1000);
+    }
+
+    #[test]
+    fn test_stable_quote_in_covers_requested_output() {
+        let env = Env::default();
+        let ss = pool(&env, stable(200));
+        let id = BytesN::from_array(&env, &[0; 32]);
+        
+        for amount_out in [1, UNIT, 50_000 * UNIT, 500_000 * UNIT] {
+            let amount_in = LiquidityPool::quote_in(&env, &ss, &ss.token_a, amount_out);
+            let out = LiquidityPool::quote_out(&env, &id, &ss, &ss.token_a, amount_in);
+            assert!(out >= amount_out);
+        }
+    }
+
+    #[test]
+    fn test_amp_ramp_interpolates() {
+        let env = Env::default();
+        let params = StableParams {
+            initial_amp: 100,
+            future_amp: 200,
+            ramp_start: 1000,
+            ramp_end: 2000,
+        };
+        
+        env.ledger().with_mut(|l| l.timestamp = 1500);
+        assert_eq!(LiquidityPool::stable_amp(&env, &params), 150);
+        env.ledger().with_mut(|l| l.timestamp = 3000);
+        assert_eq!(LiquidityPool::stable_amp(&env, &params), 200);
+    }
+
+    #[test]
+    fn test_tick_sqrt_price_roundtrip() {
+        let env = Env::default();
+        
+        assert_eq!(LiquidityPool::sqrt_price_at_tick(&env, 0), U256::from_u32(&env, 1).shl(64));
+        for tick in [MIN_TICK, -50_000, -1, 1, 6_932, 200_001, MAX_TICK] {
+            let sqrt_price = LiquidityPool::sqrt_price_at_tick(&env, tick);
+            assert!(sqrt_price > LiquidityPool::sqrt_price_at_tick(&env, tick - 1));
+            assert_eq!(LiquidityPool::tick_at_sqrt_price(&env, &sqrt_price, MIN_TICK, MAX_TICK), tick);
+        }
+        // 1.0001^6932 ~= 2
+        let price = LiquidityPool::sqrt_price_at_tick(&env, 6_932);
+        let doubled = price.mul(&price).shr(64).to_u128().unwrap();
+        assert!(doubled.abs_diff(2 << 64) < (2u128 << 64) / 1000);
+    }
+
+    #[test]
+    fn test_create_pool_canonical_order() {
+        let s = setup();
+        
+        let pool_id = s.client.create_pool(&s.token_b, &s.token_a, &30);
+        let config = s.client.get_pool(&pool_id);
+        assert_eq!(config.token_a, s.token_a);
+        assert_eq!(config.token_b, s.token_b);
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &30, &PoolCurve::ConstantProduct), Some(pool_id.clone()));
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_b, &s.token_a, &30, &PoolCurve::ConstantProduct), Some(pool_id));
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &5, &PoolCurve::ConstantProduct), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "pool already exists")]
+    fn test_create_pool_duplicate_rejected() {
+        let s = setup();
+        
+        s.client.create_pool(&s.token_a, &s.token_b, &30);
+        s.client.create_pool(&s.token_b, &s.token_a, &30);
+    }
+
+    #[test]
+    fn test_create_pool_per_fee_tier() {
+        let s = setup();
+        
+        let low = s.client.create_pool(&s.token_a, &s.token_b, &5);
+        let mid = s.client.create_pool(&s.token_a, &s.token_b, &30);
+        assert_ne!(low, mid);
+        assert_eq!(s.client.get_pair_pools(&s.token_b, &s.token_a).len(), 2);
+        assert!(s.client.try_create_pool(&s.token_a, &s.token_b, &42).is_err());
+        assert!(s.client.try_create_pool(&s.token_a, &s.token_a, &30).is_err());
+    }
+
+    #[test]
+    fn test_list_pools_paging() {
+        let s = setup();
+        let mut created = Vec::new(&s.env);
+        for fee_bps in [5u32, 30, 100] {
+            created.push_back(s.client.create_pool(&s.token_a, &s.token_b, &fee_bps));
+        }
+        
+        assert_eq!(s.client.get_total_pools(), 3);
+        let first = s.client.list_pools(&0, &2);
+        assert_eq!(first.len(), 2);
+        assert_eq!(first.get(0).unwrap().0, created.get(0).unwrap());
+        assert_eq!(first.get(1).unwrap().0, created.get(1).unwrap());
+        
+        let rest = s.client.list_pools(&2, &2);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest.get(0).unwrap().0, created.get(2).unwrap());
+        assert_eq!(rest.get(0).unwrap().1.fee_bps, 100);
+        assert_eq!(s.client.list_pools(&3, &10).len(), 0);
+        assert_eq!(s.client.list_pools(&u32::MAX, &u32::MAX).len(), 0);
+    }
+
+    #[test]
+    fn test_share_token_mint_transfer_burn() {
+        let s = setup();
+        let (pool_id, lp) = seeded_pool(&s, 30, 1_000 * UNIT, 4_000 * UNIT);
+        let config = s.client.get_pool(&pool_id);
+        let shares = LPShareTokenClient::new(&s.env, &config.share_token);
+        
+        // sqrt(1000 * 4000) units, minus the locked minimum
+        assert_eq!(config.total_shares, 2_000 * UNIT);
+        assert_eq!(shares.balance(&lp), 2_000 * UNIT - MINIMUM_LIQUIDITY);
+        assert_eq!(shares.total_supply(), config.total_shares);
+        assert_eq!(shares.admin(), s.client.address);
+        
+        // Shares are plain SEP-41 tokens: whoever holds them can redeem
+        let holder = Address::generate(&s.env);
+        shares.transfer(&lp, &holder, &(500 * UNIT));
+        assert_eq!(s.client.get_lp_position(&holder, &pool_id).shares, 500 * UNIT);
+        
+        let (amount_a, amount_b) = s.client.remove_liquidity(&holder, &pool_id, &(500 * UNIT), &0, &0);
+        assert_eq!((amount_a, amount_b), (250 * UNIT, 1_000 * UNIT));
+        assert_eq!(balance(&s.env, &s.token_a, &holder), 250 * UNIT);
+        assert_eq!(balance(&s.env, &s.token_b, &holder), 1_000 * UNIT);
+        assert_eq!(shares.balance(&holder), 0);
+        assert_eq!(shares.total_supply(), 1_500 * UNIT);
+        assert_eq!(s.client.get_pool(&pool_id).total_shares, 1_500 * UNIT);
+    }
+
+    #[test]
+    #[should_panic(expected = "insufficient shares")]
+    fn test_remove_liquidity_without_shares() {
+        let s = setup();
+        let (pool_id, _lp) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        
+        s.client.remove_liquidity(&Address::generate(&s.env), &pool_id, &UNIT, &0, &0);
+    }
+
+    #[test]
+    fn test_swap_exact_in_multi_hop() {
+        let s = setup();
+        let token_c = new_token(&s.env);
+        let (ab, _) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let bc = s.client.create_pool(&s.token_b, &token_c, &30);
+        let bc_config = s.client.get_pool(&bc);
+        if bc_config.token_a == s.token_b {
+            fund_pool(&s, &bc, 1_000 * UNIT, 2_000 * UNIT);
+        } else {
+            fund_pool(&s, &bc, 2_000 * UNIT, 1_000 * UNIT);
+        }
+        
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 10 * UNIT);
+        let path = Vec::from_array(&s.env, [s.token_a.clone(), s.token_b.clone(), token_c.clone()]);
+        let quoted = s.client.get_amounts_out(&path, &(10 * UNIT));
+        
+        let amounts = s.client.swap_exact_in(&user, &path, &(10 * UNIT), &0, &u64::MAX);
+        assert_eq!(amounts, quoted);
+        assert_eq!(amounts.len(), 3);
+        // ~0.3% fee and ~1% price impact per hop, then doubled by the B/C price
+        assert!(amounts.get(2).unwrap() > 19 * UNIT && amounts.get(2).unwrap() < 20 * UNIT);
+        assert_eq!(balance(&s.env, &s.token_a, &user), 0);
+        assert_eq!(balance(&s.env, &token_c, &user), amounts.get(2).unwrap());
+        
+        let ab_config = s.client.get_pool(&ab);
+        assert_eq!(ab_config.reserve_a, 1_010 * UNIT);
+        assert_eq!(ab_config.reserve_b, 1_000 * UNIT - amounts.get(1).unwrap());
+        assert!(s.client.try_swap_exact_in(&user, &path, &UNIT, &0, &u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_swap_exact_out_multi_hop() {
+        let s = setup();
+        let token_c = new_token(&s.env);
+        seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let bc = s.client.create_pool(&s.token_b, &token_c, &30);
+        fund_pool(&s, &bc, 1_000 * UNIT, 1_000 * UNIT);
+        
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 100 * UNIT);
+        let path = Vec::from_array(&s.env, [s.token_a.clone(), s.token_b.clone(), token_c.clone()]);
+        let quoted = s.client.get_amounts_in(&path, &(50 * UNIT));
+        let amount_in = quoted.get(0).unwrap();
+        assert!(amount_in > 50 * UNIT);
+        
+        assert!(s.client.try_swap_exact_out(&user, &path, &(50 * UNIT), &(amount_in - 1), &u64::MAX).is_err());
+        let amounts = s.client.swap_exact_out(&user, &path, &(50 * UNIT), &amount_in, &u64::MAX);
+        assert_eq!(amounts, quoted);
+        assert_eq!(balance(&s.env, &token_c, &user), 50 * UNIT);
+        assert_eq!(balance(&s.env, &s.token_a, &user), 100 * UNIT - amount_in);
+    }
+
+    #[test]
+    #[should_panic(expected = "deadline passed")]
+    fn test_swap_deadline_passed() {
+        let s = setup();
+        seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        s.env.ledger().with_mut(|l| l.timestamp = 1_000);
+        
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, UNIT);
+        let path = Vec::from_array(&s.env, [s.token_a.clone(), s.token_b.clone()]);
+        s.client.swap_exact_in(&user, &path, &UNIT, &0, &999);
+    }
+
+    #[test]
+    #[should_panic(expected = "pool reused in path")]
+    fn test_swap_path_reusing_pool_rejected() {
+        let s = setup();
+        seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        
+        let path = Vec::from_array(&s.env, [s.token_a.clone(), s.token_b.clone(), s.token_a.clone()]);
+        s.client.get_amounts_out(&path, &UNIT);
+    }
+
+    #[test]
+    fn test_swap_round_trip_uses_distinct_pools() {
+        let s = setup();
+        let (low, _) = seeded_pool(&s, 5, 1_000 * UNIT, 1_000 * UNIT);
+        let mid = s.client.create_pool(&s.token_a, &s.token_b, &30);
+        fund_pool(&s, &mid, 1_000 * UNIT, 1_000 * UNIT);
+        
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 10 * UNIT);
+        let path = Vec::from_array(&s.env, [s.token_a.clone(), s.token_b.clone(), s.token_a.clone()]);
+        let amounts = s.client.swap_exact_in(&user, &path, &(10 * UNIT), &0, &u64::MAX);
+        
+        // Cheapest pool out, the other one back, each settled at its own quote
+        let low_config = s.client.get_pool(&low);
+        let mid_config = s.client.get_pool(&mid);
+        assert_eq!(low_config.reserve_a, 1_010 * UNIT);
+        assert_eq!(low_config.reserve_b, 1_000 * UNIT - amounts.get(1).unwrap());
+        assert_eq!(mid_config.reserve_b, 1_000 * UNIT + amounts.get(1).unwrap());
+        assert_eq!(mid_config.reserve_a, 1_000 * UNIT - amounts.get(2).unwrap());
+        assert_eq!(balance(&s.env, &s.token_a, &user), amounts.get(2).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "pool not found")]
+    fn test_create_staking_pool_requires_pool() {
+        let s = setup();
+        
+        let missing = BytesN::from_array(&s.env, &[9; 32]);
+        s.client.create_staking_pool(&missing, &new_token(&s.env), &UNIT, &0);
+    }
+
+    #[test]
+    #[should_panic(expected = "staking pool already exists")]
+    fn test_create_staking_pool_duplicate_rejected() {
+        let s = setup();
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let reward_token = new_token(&s.env);
+        
+        s.client.create_staking_pool(&pool_id, &reward_token, &UNIT, &0);
+        s.client.create_staking_pool(&pool_id, &reward_token, &(2 * UNIT), &0);
+    }
+
+    #[test]
+    fn test_staking_rewards_pro_rata() {
+        let s = setup();
+        let (pool_id, lp) = seeded_pool(&s, 30, 2_000 * UNIT, 2_000 * UNIT);
+        let reward_token = new_token(&s.env);
+        s.client.create_staking_pool(&pool_id, &reward_token, &(10 * UNIT), &0);
+        let funder = Address::generate(&s.env);
+        mint(&s.env, &reward_token, &funder, 1_000 * UNIT);
+        s.client.fund_staking_pool(&funder, &pool_id, &(1_000 * UNIT));
+        
+        let other = Address::generate(&s.env);
+        let share_token = token::Client::new(&s.env, &s.client.get_pool(&pool_id).share_token);
+        share_token.transfer(&lp, &other, &(250 * UNIT));
+        s.client.stake_lp(&lp, &pool_id, &(750 * UNIT));
+        s.client.stake_lp(&other, &pool_id, &(250 * UNIT));
+        
+        s.env.ledger().with_mut(|l| l.timestamp += 10);
+        assert_eq!(s.client.pending_rewards(&lp, &pool_id), 75 * UNIT);
+        assert_eq!(s.client.pending_rewards(&other, &pool_id), 25 * UNIT);
+        
+        assert_eq!(s.client.claim_staking_rewards(&other, &pool_id), 25 * UNIT);
+        assert_eq!(balance(&s.env, &reward_token, &other), 25 * UNIT);
+        assert_eq!(s.client.get_staking_pool(&pool_id).reward_budget, 900 * UNIT);
+        
+        // Emission stops once the budget is spent
+        s.env.ledger().with_mut(|l| l.timestamp += 1_000);
+        assert_eq!(s.client.pending_rewards(&lp, &pool_id), 750 * UNIT);
+        assert_eq!(s.client.pending_rewards(&other, &pool_id), 250 * UNIT - 25 * UNIT);
+    }
+
+    #[test]
+    fn test_staking_rewards_large_emission() {
+        let s = setup();
+        let (pool_id, lp) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let reward_token = new_token(&s.env);
+        let rate = 10i128.pow(29);
+        s.client.create_staking_pool(&pool_id, &reward_token, &rate, &0);
+        let funder = Address::generate(&s.env);
+        mint(&s.env, &reward_token, &funder, 10 * rate);
+        s.client.fund_staking_pool(&funder, &pool_id, &(10 * rate));
+        
+        // emitted * REWARD_PRECISION alone would exceed i128
+        s.client.stake_lp(&lp, &pool_id, &UNIT);
+        s.env.ledger().with_mut(|l| l.timestamp += 1);
+        let pending = s.client.pending_rewards(&lp, &pool_id);
+        assert!(pending <= rate && rate - pending < rate / 1_000_000);
+    }
+
+    // Pool seeded with 2000 shares per side and a staking pool with `lock_period`;
+    // returns the LP holding all unlocked shares
+    fn staked_setup(s: &Setup, lock_period: u64) -> (BytesN<32>, Address) {
+        let (pool_id, lp) = seeded_pool(s, 30, 2_000 * UNIT, 2_000 * UNIT);
+        s.client.create_staking_pool(&pool_id, &new_token(&s.env), &0, &lock_period);
+        (pool_id, lp)
+    }
+
+    #[test]
+    fn test_stake_lock_enforced() {
+        let s = setup();
+        let (pool_id, lp) = staked_setup(&s, 3_600);
+        s.client.stake_lp(&lp, &pool_id, &(1_000 * UNIT));
+        
+        assert!(s.client.try_unstake_lp(&lp, &pool_id, &UNIT).is_err());
+        assert!(s.client.try_emergency_withdraw(&lp, &pool_id).is_err());
+        
+        s.env.ledger().with_mut(|l| l.timestamp += 3_600);
+        assert_eq!(s.client.unstake_lp(&lp, &pool_id, &(400 * UNIT)), 400 * UNIT);
+        assert_eq!(s.client.emergency_withdraw(&lp, &pool_id), 600 * UNIT);
+        assert_eq!(s.client.get_staking_pool(&pool_id).total_staked, 0);
+    }
+
+    #[test]
+    fn test_early_exit_penalty_burn() {
+        let s = setup();
+        let (pool_id, lp) = staked_setup(&s, 3_600);
+        s.client.set_early_exit_penalty(&pool_id, &1_000, &PenaltyMode::Burn);
+        s.client.stake_lp(&lp, &pool_id, &(1_000 * UNIT));
+        let shares_before = s.client.get_pool(&pool_id).total_shares;
+        
+        assert_eq!(s.client.unstake_lp(&lp, &pool_id, &(500 * UNIT)), 450 * UNIT);
+        assert_eq!(s.client.get_pool(&pool_id).total_shares, shares_before - 50 * UNIT);
+        
+        // Emergency exit pays the same penalty on the rest
+        assert_eq!(s.client.emergency_withdraw(&lp, &pool_id), 450 * UNIT);
+        assert_eq!(s.client.get_pool(&pool_id).total_shares, shares_before - 100 * UNIT);
+        // Burned shares leave their tokens behind for the remaining holders
+        assert_eq!(s.client.get_pool(&pool_id).reserve_a, 2_000 * UNIT);
+    }
+
+    #[test]
+    fn test_early_exit_penalty_redistribute() {
+        let s = setup();
+        let (pool_id, lp) = staked_setup(&s, 3_600);
+        s.client.set_early_exit_penalty(&pool_id, &1_000, &PenaltyMode::Redistribute);
+        let other = Address::generate(&s.env);
+        let share_token = token::Client::new(&s.env, &s.client.get_pool(&pool_id).share_token);
+        share_token.transfer(&lp, &other, &(300 * UNIT));
+        s.client.stake_lp(&lp, &pool_id, &(900 * UNIT));
+        s.client.stake_lp(&other, &pool_id, &(300 * UNIT));
+        let shares_before = s.client.get_pool(&pool_id).total_shares;
+        
+        // 10% of 300 goes to the only remaining staker
+        assert_eq!(s.client.emergency_withdraw(&other, &pool_id), 270 * UNIT);
+        assert_eq!(s.client.get_pool(&pool_id).total_shares, shares_before);
+        
+        s.env.ledger().with_mut(|l| l.timestamp += 3_600);
+        let returned = s.client.emergency_withdraw(&lp, &pool_id);
+        assert!(930 * UNIT - returned <= 1); // accumulator rounds down
+    }
+
+    #[test]
+    fn test_enable_fee_tier_bounds() {
+        let s = setup();
+        
+        s.client.enable_fee_tier(&MAX_FEE_BPS);
+        assert!(s.client.get_fee_tiers().contains(MAX_FEE_BPS));
+        assert!(s.client.try_enable_fee_tier(&0).is_err());
+        assert!(s.client.try_enable_fee_tier(&(MAX_FEE_BPS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_set_fee_bps_moves_pool_between_tiers() {
+        let s = setup();
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let other = s.client.create_pool(&s.token_a, &s.token_b, &100);
+        
+        assert!(s.client.try_set_fee_bps(&pool_id, &42).is_err());
+        // Another constant-product pool already charges 1%
+        assert!(s.client.try_set_fee_bps(&pool_id, &100).is_err());
+        
+        s.client.set_fee_bps(&pool_id, &5);
+        assert_eq!(s.client.get_pool(&pool_id).fee_bps, 5);
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &5, &PoolCurve::ConstantProduct), Some(pool_id.clone()));
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &30, &PoolCurve::ConstantProduct), None);
+        assert!(s.client.try_create_pool(&s.token_a, &s.token_b, &5).is_err());
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &100, &PoolCurve::ConstantProduct), Some(other));
+        
+        // The vacated tier takes a new pool under a fresh ID
+        let reopened = s.client.create_pool(&s.token_a, &s.token_b, &30);
+        assert_ne!(reopened, pool_id);
+        assert_eq!(s.client.get_pool(&reopened).fee_bps, 30);
+        assert_eq!(s.client.get_pool(&pool_id).fee_bps, 5);
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &30, &PoolCurve::ConstantProduct), Some(reopened.clone()));
+        fund_pool(&s, &reopened, 10 * UNIT, 10 * UNIT);
+        assert_ne!(s.client.get_pool(&reopened).share_token, s.client.get_pool(&pool_id).share_token);
+    }
+
+    #[test]
+    fn test_protocol_fee_accrual_and_collection() {
+        let s = setup();
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let treasury = Address::generate(&s.env);
+        s.client.set_protocol_fee(&2_000);
+        assert!(s.client.try_set_protocol_fee(&(MAX_PROTOCOL_FEE_BPS + 1)).is_err());
+        assert!(s.client.try_collect_protocol_fees(&pool_id).is_err());
+        s.client.set_treasury(&treasury);
+        
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 100 * UNIT);
+        let out = s.client.swap(&user, &pool_id, &s.token_a, &(100 * UNIT), &0);
+        
+        // 0.3% fee of which 20% goes to the protocol, held outside reserves
+        let fees = s.client.get_pool_fees(&pool_id);
+        assert_eq!(fees.total_fees_a, 3_000_000);
+        assert_eq!(fees.protocol_fees_a, 600_000);
+        assert_eq!(fees.uncollected_a, 600_000);
+        let config = s.client.get_pool(&pool_id);
+        assert_eq!(config.reserve_a, 1_100 * UNIT - 600_000);
+        assert_eq!(config.reserve_b, 1_000 * UNIT - out);
+        
+        assert_eq!(s.client.collect_protocol_fees(&pool_id), (600_000, 0));
+        assert_eq!(balance(&s.env, &s.token_a, &treasury), 600_000);
+        assert_eq!(s.client.get_pool_fees(&pool_id).uncollected_a, 0);
+        assert_eq!(s.client.collect_protocol_fees(&pool_id), (0, 0));
+        
+        // Reserves stay fully backed by the contract's balance
+        assert_eq!(balance(&s.env, &s.token_a, &s.client.address), config.reserve_a);
+    }
+
+    #[test]
+    fn test_consult_time_weighted_average() {
+        let s = setup();
+        s.env.ledger().with_mut(|l| l.timestamp = 1_000);
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        assert!(s.client.try_consult(&pool_id, &s.token_a, &UNIT, &60).is_err());
+        
+        // Price 1.0 for 10 minutes, then moved by a large swap
+        s.env.ledger().with_mut(|l| l.timestamp = 1_600);
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 1_000 * UNIT);
+        s.client.swap(&user, &pool_id, &s.token_a, &(1_000 * UNIT), &0);
+        let config = s.client.get_pool(&pool_id);
+        let price_a = config.reserve_b * PRICE_PRECISION / config.reserve_a;
+        let price_b = config.reserve_a * PRICE_PRECISION / config.reserve_b;
+        
+        s.env.ledger().with_mut(|l| l.timestamp = 2_200);
+        let expected_a = UNIT * ((PRICE_PRECISION + price_a) / 2) / PRICE_PRECISION;
+        let expected_b = UNIT * ((PRICE_PRECISION + price_b) / 2) / PRICE_PRECISION;
+        assert_eq!(s.client.consult(&pool_id, &s.token_a, &UNIT, &1_200), expected_a);
+        assert_eq!(s.client.consult(&pool_id, &s.token_b, &UNIT, &1_200), expected_b);
+        
+        // A window inside the second period sees only the new price
+        assert_eq!(s.client.consult(&pool_id, &s.token_a, &UNIT, &600), UNIT * price_a / PRICE_PRECISION);
+        
+        assert!(s.client.try_consult(&pool_id, &s.token_a, &UNIT, &1_201).is_err());
+        assert!(s.client.try_consult(&pool_id, &s.token_a, &UNIT, &0).is_err());
+    }
+
+    #[test]
+    fn test_get_pool_by_tokens_per_curve() {
+        let s = setup();
+        
+        let cp = s.client.create_pool(&s.token_a, &s.token_b, &5);
+        let ss = s.client.create_stable_pool(&s.token_a, &s.token_b, &5, &100);
+        assert_ne!(cp, ss);
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_b, &s.token_a, &5, &PoolCurve::ConstantProduct), Some(cp));
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_b, &s.token_a, &5, &PoolCurve::StableSwap), Some(ss));
+        assert_eq!(s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &5, &PoolCurve::Concentrated), None);
+        assert!(s.client.try_create_stable_pool(&s.token_a, &s.token_b, &5, &200).is_err());
+    }
+
+    #[test]
+    fn test_minimum_liquidity_locked() {
+        let s = setup();
+        let (pool_id, lp) = seeded_pool(&s, 30, 100 * UNIT, 100 * UNIT);
+        let config = s.client.get_pool(&pool_id);
+        let share_token = token::Client::new(&s.env, &config.share_token);
+        assert_eq!(share_token.balance(&s.client.address), MINIMUM_LIQUIDITY);
+        
+        // Withdrawing every redeemable share leaves the locked slice behind
+        let shares = share_token.balance(&lp);
+        s.client.remove_liquidity(&lp, &pool_id, &shares, &0, &0);
+        let config = s.client.get_pool(&pool_id);
+        assert_eq!(config.total_shares, MINIMUM_LIQUIDITY);
+        assert_eq!(config.reserve_a, MINIMUM_LIQUIDITY);
+        assert_eq!(config.reserve_b, MINIMUM_LIQUIDITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "insufficient initial liquidity")]
+    fn test_minimum_liquidity_first_deposit_too_small() {
+        let s = setup();
+        seeded_pool(&s, 30, 1_000, 1_000);
+    }
+
+    #[test]
+    fn test_add_liquidity_pulls_optimal_amounts() {
+        let s = setup();
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 2_000 * UNIT);
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 100 * UNIT);
+        mint(&s.env, &s.token_b, &user, 100 * UNIT);
+        
+        // B is the binding side at a 1:2 ratio; the unused A stays with the user
+        let (amount_a, amount_b, shares) =
+            s.client.add_liquidity(&user, &pool_id, &(100 * UNIT), &(100 * UNIT), &0, &0);
+        assert_eq!((amount_a, amount_b), (50 * UNIT, 100 * UNIT));
+        assert_eq!(balance(&s.env, &s.token_a, &user), 50 * UNIT);
+        assert_eq!(balance(&s.env, &s.token_b, &user), 0);
+        assert_eq!(s.client.get_lp_position(&user, &pool_id).shares, shares);
+        
+        let config = s.client.get_pool(&pool_id);
+        assert_eq!((config.reserve_a, config.reserve_b), (1_050 * UNIT, 2_100 * UNIT));
+    }
+
+    #[test]
+    #[should_panic(expected = "insufficient a amount")]
+    fn test_add_liquidity_respects_minimums() {
+        let s = setup();
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 2_000 * UNIT);
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 100 * UNIT);
+        mint(&s.env, &s.token_b, &user, 100 * UNIT);
+        
+        s.client.add_liquidity(&user, &pool_id, &(100 * UNIT), &(100 * UNIT), &(60 * UNIT), &0);
+    }
+
+    #[test]
+    fn test_zap_round_trip() {
+        let s = setup();
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 100 * UNIT);
+        
+        let shares = s.client.zap_in(&user, &pool_id, &s.token_a, &(100 * UNIT), &0);
+        assert_eq!(s.client.get_lp_position(&user, &pool_id).shares, shares);
+        // The optimal split leaves at most rounding dust to refund
+        let dust_a = balance(&s.env, &s.token_a, &user);
+        assert!(dust_a <= 10);
+        assert!(balance(&s.env, &s.token_b, &user) <= 10);
+        
+        let out = s.client.zap_out(&user, &pool_id, &shares, &s.token_a, &0);
+        assert_eq!(balance(&s.env, &s.token_a, &user), dust_a + out);
+        // Only the swap fees on the swapped halves are lost
+        assert!(out > 99 * UNIT && out < 100 * UNIT);
+        assert_eq!(s.client.get_lp_position(&user, &pool_id).shares, 0);
+        
+        let config = s.client.get_pool(&pool_id);
+        assert_eq!(balance(&s.env, &s.token_a, &s.client.address), config.reserve_a);
+        assert_eq!(balance(&s.env, &s.token_b, &s.client.address), config.reserve_b);
+    }
+
+    #[test]
+    fn test_zap_slippage_and_token_checks() {
+        let s = setup();
+        let (pool_id, lp) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 100 * UNIT);
+        
+        assert!(s.client.try_zap_in(&user, &pool_id, &s.token_a, &(100 * UNIT), &(100 * UNIT)).is_err());
+        let stranger = new_token(&s.env);
+        assert!(s.client.try_zap_out(&lp, &pool_id, &UNIT, &stranger, &0).is_err());
+        assert!(s.client.try_zap_out(&lp, &pool_id, &UNIT, &s.token_b, &(2 * UNIT)).is_err());
+    }
+
+    // Range pool on the setup pair at price 1.0 with tick spacing 10
+    fn range_pool(s: &Setup) -> BytesN<32> {
+        s.client.create_range_pool(&s.token_a, &s.token_b, &30, &10, &PRICE_PRECISION)
+    }
+
+    // Open a position on [lower, upper) and fund it with up to `amount` of each token
+    fn range_position(s: &Setup, pool_id: &BytesN<32>, owner: &Address, lower: i32, upper: i32, amount: i128) -> (u64, i128) {
+        mint(&s.env, &s.token_a, owner, amount);
+        mint(&s.env, &s.token_b, owner, amount);
+        let position_id = s.client.open_position(owner, pool_id, &lower, &upper);
+        let (liquidity, _, _) = s.client.increase_liquidity(owner, &position_id, &amount, &amount, &0);
+        (position_id, liquidity)
+    }
+
+    #[test]
+    fn test_range_swap_crosses_initialized_tick() {
+        let s = setup();
+        let pool_id = range_pool(&s);
+        let lp = Address::generate(&s.env);
+        let (_, wide) = range_position(&s, &pool_id, &lp, -1_000, 1_000, 1_000 * UNIT);
+        let (_, narrow) = range_position(&s, &pool_id, &lp, -100, 100, 100 * UNIT);
+        assert_eq!(s.client.get_range_state(&pool_id).liquidity, wide + narrow);
+        assert_eq!(
+            s.client.get_pool_by_tokens(&s.token_a, &s.token_b, &30, &PoolCurve::Concentrated),
+            Some(pool_id.clone())
+        );
+        
+        // Selling A pushes the price below the narrow range, dropping its liquidity
+        let trader = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &trader, 300 * UNIT);
+        let quoted = s.client.get_swap_quote(&pool_id, &s.token_a, &(300 * UNIT));
+        let out = s.client.swap(&trader, &pool_id, &s.token_a, &(300 * UNIT), &0);
+        assert_eq!(out, quoted);
+        let state = s.client.get_range_state(&pool_id);
+        assert!(state.tick < -100);
+        assert_eq!(state.liquidity, wide);
+        
+        // Buying it back re-enters the range and adds the liquidity again
+        mint(&s.env, &s.token_b, &trader, 300 * UNIT);
+        s.client.swap(&trader, &pool_id, &s.token_b, &(300 * UNIT), &0);
+        let state = s.client.get_range_state(&pool_id);
+        assert!(state.tick >= -100 && state.tick < 100);
+        assert_eq!(state.liquidity, wide + narrow);
+    }
+
+    #[test]
+    fn test_range_fees_split_per_position() {
+        let s = setup();
+        let pool_id = range_pool(&s);
+        let small_lp = Address::generate(&s.env);
+        let large_lp = Address::generate(&s.env);
+        let idle_lp = Address::generate(&s.env);
+        let (small, small_liq) = range_position(&s, &pool_id, &small_lp, -500, 500, 100 * UNIT);
+        let (large, large_liq) = range_position(&s, &pool_id, &large_lp, -500, 500, 300 * UNIT);
+        let (idle, _) = range_position(&s, &pool_id, &idle_lp, 1_000, 2_000, 100 * UNIT);
+        
+        let trader = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &trader, 10 * UNIT);
+        s.client.swap(&trader, &pool_id, &s.token_a, &(10 * UNIT), &0);
+        
+        // LP share of the 0.3% fee, pro rata to in-range liquidity
+        let (small_fees, _) = s.client.get_position_fees(&small);
+        let (large_fees, _) = s.client.get_position_fees(&large);
+        assert_eq!(s.client.get_position_fees(&idle), (0, 0));
+        assert!(small_fees > 0);
+        assert!((large_fees * small_liq - small_fees * large_liq).abs() <= large_liq);
+        assert!(small_fees + large_fees <= 300_000 && small_fees + large_fees >= 299_990);
+        
+        assert_eq!(s.client.collect_fees(&small_lp, &small), (small_fees, 0));
+        assert_eq!(balance(&s.env, &s.token_a, &small_lp), small_fees);
+        assert_eq!(s.client.get_position_fees(&small), (0, 0));
+        assert_eq!(s.client.get_position_fees(&large).0, large_fees);
+    }
+
+    #[test]
+    fn test_range_decrease_liquidity_payouts() {
+        let s = setup();
+        let pool_id = range_pool(&s);
+        let lp = Address::generate(&s.env);
+        
+        // Below the current price a range holds only token_b
+        mint(&s.env, &s.token_b, &lp, 100 * UNIT);
+        let below = s.client.open_position(&lp, &pool_id, &-200, &-100);
+        let (liquidity, amount_a, amount_b) = s.client.increase_liquidity(&lp, &below, &0, &(100 * UNIT), &0);
+        assert_eq!(amount_a, 0);
+        assert!(100 * UNIT - amount_b <= 1);
+        let (out_a, out_b) = s.client.decrease_liquidity(&lp, &below, &liquidity, &0, &0);
+        assert_eq!(out_a, 0);
+        assert!(amount_b - out_b <= 1);
+        
+        // In range: half the liquidity returns half of each side
+        let (inside, liquidity) = range_position(&s, &pool_id, &lp, -100, 100, 100 * UNIT);
+        let position = s.client.get_position(&inside);
+        assert_eq!(position.liquidity, liquidity);
+        let before_a = balance(&s.env, &s.token_a, &lp);
+        let (half_a, half_b) = s.client.decrease_liquidity(&lp, &inside, &(liquidity / 2), &0, &0);
+        assert!((half_a - 50 * UNIT).abs() <= 1 && (half_b - 50 * UNIT).abs() <= 1);
+        assert_eq!(balance(&s.env, &s.token_a, &lp), before_a + half_a);
+        assert_eq!(s.client.get_position(&inside).liquidity, liquidity - liquidity / 2);
+        
+        assert!(s.client.try_decrease_liquidity(&lp, &inside, &liquidity, &0, &0).is_err());
+        assert!(s.client.try_close_position(&lp, &inside).is_err());
+        s.client.decrease_liquidity(&lp, &inside, &(liquidity - liquidity / 2), &0, &0);
+        s.client.close_position(&lp, &inside);
+        assert!(!s.client.get_positions(&lp).contains(inside));
+    }
+
+    const REPAY: u8 = 0;
+    const UNDERPAY: u8 = 1;
+    const REENTER_SWAP: u8 = 2;
+    const REENTER_REMOVE: u8 = 3;
+
+    // Flash loan borrower whose behaviour is picked by the first data byte
+    #[contract]
+    struct Borrower;
+
+    #[contractimpl]
+    impl Borrower {
+        pub fn init(env: Env, lender: Address, pool_id: BytesN<32>) {
+            env.storage().instance().set(&symbol_short!("target"), &(lender, pool_id));
+        }
+    }
+
+    #[contractimpl]
+    impl FlashLoanReceiver for Borrower {
+        fn on_flash_loan(env: Env, token: Address, amount: i128, fee: i128, data: Bytes) {
+            let (lender, pool_id): (Address, BytesN<32>) = env.storage().instance()
+                .get(&symbol_short!("target"))
+                .unwrap();
+            let me = env.current_contract_address();
+            let pool = LiquidityPoolClient::new(&env, &lender);
+            let mode = data.get(0).unwrap_or(REPAY);
+            
+            match mode {
+                REENTER_SWAP => {
+                    pool.swap(&me, &pool_id, &token, &amount, &0);
+                }
+                REENTER_REMOVE => {
+                    pool.remove_liquidity(&me, &pool_id, &1, &0, &0);
+                }
+                _ => {}
+            }
+            
+            let repay = if mode == UNDERPAY { amount + fee - 1 } else { amount + fee };
+            token::Client::new(&env, &token).transfer(&me, &lender, &repay);
+        }
+    }
+
+    // Helper: seeded pool and a borrower funded to cover one loan fee
+    fn flash_setup(s: &Setup, fee: i128) -> (BytesN<32>, Address) {
+        let (pool_id, _) = seeded_pool(s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let borrower = s.env.register_contract(None, Borrower);
+        BorrowerClient::new(&s.env, &borrower).init(&s.client.address, &pool_id);
+        mint(&s.env, &s.token_a, &borrower, fee);
+        (pool_id, borrower)
+    }
+
+    #[test]
+    fn test_flash_loan_fee_accrues_to_lps_and_protocol() {
+        let s = setup();
+        let fee = 3_000_000; // 0.3% of 100 UNIT
+        let (pool_id, borrower) = flash_setup(&s, fee);
+        s.client.set_protocol_fee(&2_000);
+        let data = Bytes::from_array(&s.env, &[REPAY]);
+        
+        let paid = s.client.flash_loan(&borrower, &pool_id, &s.token_a, &(100 * UNIT), &data);
+        assert_eq!(paid, fee);
+        assert_eq!(balance(&s.env, &s.token_a, &borrower), 0);
+        
+        // 80% of the fee grows the reserves, 20% is held for the protocol
+        let config = s.client.get_pool(&pool_id);
+        assert_eq!(config.reserve_a, 1_000 * UNIT + 2_400_000);
+        assert_eq!(config.reserve_b, 1_000 * UNIT);
+        let fees = s.client.get_pool_fees(&pool_id);
+        assert_eq!(fees.total_fees_a, fee);
+        assert_eq!(fees.protocol_fees_a, 600_000);
+        assert_eq!(fees.uncollected_a, 600_000);
+        assert_eq!(balance(&s.env, &s.token_a, &s.client.address), config.reserve_a + 600_000);
+        
+        // Loans are capped by the reserve of the borrowed token
+        assert!(s.client.try_flash_loan(&borrower, &pool_id, &s.token_a, &(config.reserve_a + 1), &data).is_err());
+        assert!(s.client.try_flash_loan(&borrower, &pool_id, &s.token_a, &0, &data).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "flash loan not repaid")]
+    fn test_flash_loan_rejects_underpayment() {
+        let s = setup();
+        let (pool_id, borrower) = flash_setup(&s, 3_000_000);
+        let data = Bytes::from_array(&s.env, &[UNDERPAY]);
+        s.client.flash_loan(&borrower, &pool_id, &s.token_a, &(100 * UNIT), &data);
+    }
+
+    #[test]
+    fn test_flash_loan_blocks_reentrant_calls() {
+        let s = setup();
+        let (pool_id, borrower) = flash_setup(&s, 3_000_000);
+        let before = s.client.get_pool(&pool_id);
+        
+        // The host refuses to re-enter the pool from the callback; nothing moves
+        for mode in [REENTER_SWAP, REENTER_REMOVE] {
+            let data = Bytes::from_array(&s.env, &[mode]);
+            assert!(s.client.try_flash_loan(&borrower, &pool_id, &s.token_a, &(100 * UNIT), &data).is_err());
+        }
+        let after = s.client.get_pool(&pool_id);
+        assert_eq!((after.reserve_a, after.reserve_b), (before.reserve_a, before.reserve_b));
+        assert_eq!(balance(&s.env, &s.token_a, &borrower), 3_000_000);
+        assert_eq!(s.client.get_pool_fees(&pool_id).total_fees_a, 0);
+    }
+
+    #[test]
+    fn test_flash_loan_lock_blocks_swap_and_remove_liquidity() {
+        let s = setup();
+        let (pool_id, lp) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, UNIT);
+        
+        // Held for the duration of a loan callback
+        s.env.as_contract(&s.client.address, || {
+            s.env.storage().instance().set(&DataKey::FlashLoanLock, &true);
+        });
+        assert!(s.client.try_swap(&user, &pool_id, &s.token_a, &UNIT, &0).is_err());
+        assert!(s.client.try_remove_liquidity(&lp, &pool_id, &UNIT, &0, &0).is_err());
+        
+        s.env.as_contract(&s.client.address, || {
+            s.env.storage().instance().remove(&DataKey::FlashLoanLock);
+        });
+        assert!(s.client.swap(&user, &pool_id, &s.token_a, &UNIT, &0) > 0);
+        assert!(s.client.remove_liquidity(&lp, &pool_id, &UNIT, &0, &0).0 > 0);
+    }
+
+    #[test]
+    fn test_pool_stats_roll_over_after_24h() {
+        let s = setup();
+        let (pool_id, _) = seeded_pool(&s, 30, 1_000 * UNIT, 1_000 * UNIT);
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, 100 * UNIT);
+        mint(&s.env, &s.token_b, &user, 10 * UNIT);
+        
+        s.client.swap(&user, &pool_id, &s.token_a, &(100 * UNIT), &0);
+        let stats = s.client.get_pool_stats(&pool_id);
+        assert_eq!((stats.volume_24h_a, stats.fees_24h_a), (100 * UNIT, 3_000_000));
+        assert_eq!(stats.tvl_b, stats.reserve_b * 2);
+        assert_eq!(stats.fee_apr_bps, 3_000_000 * stats.price_a / PRICE_PRECISION * 365 * 10000 / stats.tvl_b);
+        
+        // Still inside the window 23 hours later
+        s.env.ledger().with_mut(|l| l.timestamp += 23 * 3_600);
+        s.client.swap(&user, &pool_id, &s.token_b, &(10 * UNIT), &0);
+        let stats = s.client.get_pool_stats(&pool_id);
+        assert_eq!((stats.volume_24h_a, stats.volume_24h_b), (100 * UNIT, 10 * UNIT));
+        assert_eq!(stats.fees_24h_b, 300_000);
+        
+        // The first hour's bucket drops out after 24 hours
+        s.env.ledger().with_mut(|l| l.timestamp += 3_600);
+        let stats = s.client.get_pool_stats(&pool_id);
+        assert_eq!((stats.volume_24h_a, stats.fees_24h_a), (0, 0));
+        assert_eq!((stats.volume_24h_b, stats.fees_24h_b), (10 * UNIT, 300_000));
+        assert_eq!(stats.fee_apr_bps, 300_000 * 365 * 10000 / stats.tvl_b);
+        
+        s.env.ledger().with_mut(|l| l.timestamp += 23 * 3_600);
+        let stats = s.client.get_pool_stats(&pool_id);
+        assert_eq!((stats.volume_24h_b, stats.fee_apr_bps), (0, 0));
+    }
+
+    #[test]
+    fn test_staking_apr_prices_reward_token() {
+        let s = setup();
+        let (pool_id, lp) = seeded_pool(&s, 30, 2_000 * UNIT, 2_000 * UNIT);
+        let reward_token = new_token(&s.env);
+        s.client.create_staking_pool(&pool_id, &reward_token, &UNIT, &0);
+        let funder = Address::generate(&s.env);
+        mint(&s.env, &reward_token, &funder, 100_000_000 * UNIT);
+        s.client.fund_staking_pool(&funder, &pool_id, &(100_000_000 * UNIT));
+        assert_eq!(s.client.get_staking_apr(&pool_id), 0);
+        
+        // Nothing pairs the reward token with either side yet
+        s.client.stake_lp(&lp, &pool_id, &(1_000 * UNIT));
+        assert_eq!(s.client.get_staking_apr(&pool_id), 0);
+        
+        // A dust pool at 1:4 is registered first, but the deeper 1:1 pool sets
+        // the price: one reward token per second against half the 4000 token_b TVL
+        let dust_pool = s.client.create_pool(&reward_token, &s.token_b, &30);
+        let (dust_reward, dust_b) = if reward_token < s.token_b { (UNIT, 4 * UNIT) } else { (4 * UNIT, UNIT) };
+        fund_pool(&s, &dust_pool, dust_reward, dust_b);
+        let reward_pool = s.client.create_pool(&reward_token, &s.token_b, &5);
+        fund_pool(&s, &reward_pool, 1_000 * UNIT, 1_000 * UNIT);
+        assert_eq!(s.client.get_staking_apr(&pool_id), SECONDS_PER_YEAR * UNIT * 10000 / (2_000 * UNIT));
+        
+        // An oversized rate is capped by the remaining budget instead of overflowing
+        s.client.set_reward_rate(&pool_id, &10i128.pow(37));
+        assert_eq!(s.client.get_staking_apr(&pool_id), 100_000_000 * UNIT * 10000 / (2_000 * UNIT));
+    }
+
+    #[test]
+    fn test_swap_fee_math_handles_large_amounts() {
+        let s = setup();
+        let reserve = 10i128.pow(35);
+        let (pool_id, _) = seeded_pool(&s, 30, reserve, reserve);
+        s.client.set_protocol_fee(&2_000);
+        
+        // amount_in * (10000 - fee) alone would exceed i128
+        let user = Address::generate(&s.env);
+        mint(&s.env, &s.token_a, &user, reserve);
+        let quoted = s.client.get_swap_quote(&pool_id, &s.token_a, &reserve);
+        let out = s.client.swap(&user, &pool_id, &s.token_a, &reserve, &0);
+        assert_eq!(out, quoted);
+        // 0.997 / 1.997 of the output reserve
+        assert!(out > reserve / 1_000 * 499 && out < reserve / 2);
+        
+        let fees = s.client.get_pool_fees(&pool_id);
+        assert_eq!(fees.total_fees_a, reserve / 10_000 * 30);
+        assert_eq!(fees.protocol_fees_a, reserve / 10_000 * 6);
+    }
+
+    #[test]
+    fn test_next_initialized_tick() {
+        let env = Env::default();
+        let ticks = Vec::from_array(&env, [-200, -100, 0, 100]);
+        
+        // Selling token_a moves down and includes the current tick
+        assert_eq!(LiquidityPool::next_initialized_tick(&ticks, 0, true), Some(0));
+        assert_eq!(LiquidityPool::next_initialized_tick(&ticks, -1, true), Some(-100));
+        assert_eq!(LiquidityPool::next_initialized_tick(&ticks, -201, true), None);
+        
+        // Buying it moves strictly up
+        assert_eq!(LiquidityPool::next_initialized_tick(&ticks, 0, false), Some(100));
+        assert_eq!(LiquidityPool::next_initialized_tick(&ticks, -150, false), Some(-100));
+        assert_eq!(LiquidityPool::next_initialized_tick(&ticks, 100, false), None);
+        assert_eq!(LiquidityPool::next_initialized_tick(&Vec::new(&env), 0, true), None);
     }
 }
@@ -0,0 +1,253 @@
+#![no_std]
+
+use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Address, Env, String};
+
+/// Storage keys for the contract
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    TotalSupply,
+    Balance(Address),
+    Allowance(Address, Address),
+    Initialized,
+    Decimals,
+    Name,
+    Symbol,
+}
+
+/// Allowance amount with ledger expiration
+#[derive(Clone)]
+#[contracttype]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
+
+/// LP Share Token - SEP-41 token deployed per liquidity pool.
+/// The pool contract is the admin and the only account able to mint.
+#[contract]
+pub struct LPShareToken;
+
+#[contractimpl]
+impl LPShareToken {
+    /// Initialize the share token (called by the pool that deployed it).
+    /// Takes no auth: the pool deploys and initializes in the same call, so
+    /// nobody else can reach an uninitialized instance first.
+    pub fn initialize(env: Env, admin: Address, decimals: u32, name: String, symbol: String) {
+        if env.storage().instance().has(&DataKey::Initialized) {
+            panic!("already initialized");
+        }
+        
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::Decimals, &decimals);
+        env.storage().instance().set(&DataKey::Name, &name);
+        env.storage().instance().set(&DataKey::Symbol, &symbol);
+        env.storage().instance().set(&DataKey::TotalSupply, &0i128);
+        env.storage().instance().set(&DataKey::Initialized, &true);
+    }
+    
+    /// Get token name
+    pub fn name(env: Env) -> String {
+        env.storage().instance().get(&DataKey::Name).unwrap()
+    }
+    
+    /// Get token symbol
+    pub fn symbol(env: Env) -> String {
+        env.storage().instance().get(&DataKey::Symbol).unwrap()
+    }
+    
+    /// Get decimals
+    pub fn decimals(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::Decimals).unwrap_or(7u32)
+    }
+    
+    /// Get total supply
+    pub fn total_supply(env: Env) -> i128 {
+        env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0)
+    }
+    
+    /// Get balance of an address
+    pub fn balance(env: Env, id: Address) -> i128 {
+        env.storage().persistent().get(&DataKey::Balance(id)).unwrap_or(0)
+    }
+    
+    /// Get allowance
+    pub fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+        Self::read_allowance(&env, from, spender).amount
+    }
+    
+    /// Approve spender to spend tokens
+    pub fn approve(env: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        from.require_auth();
+        
+        if amount < 0 {
+            panic!("amount cannot be negative");
+        }
+        if amount > 0 && expiration_ledger < env.ledger().sequence() {
+            panic!("expiration ledger is in the past");
+        }
+        
+        let key = DataKey::Allowance(from.clone(), spender.clone());
+        env.storage().temporary().set(&key, &AllowanceValue { amount, expiration_ledger });
+        if amount > 0 {
+            let live_for = expiration_ledger - env.ledger().sequence();
+            env.storage().temporary().extend_ttl(&key, live_for, live_for);
+        }
+        
+        env.events().publish(
+            (symbol_short!("approve"), from, spender),
+            (amount, expiration_ledger),
+        );
+    }
+    
+    /// Transfer tokens from sender to recipient
+    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+        
+        Self::spend_balance(&env, from.clone(), amount);
+        Self::receive_balance(&env, to.clone(), amount);
+        
+        env.events().publish(
+            (symbol_short!("transfer"), from, to),
+            amount,
+        );
+    }
+    
+    /// Transfer tokens using allowance
+    pub fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+        
+        Self::spend_allowance(&env, from.clone(), spender, amount);
+        Self::spend_balance(&env, from.clone(), amount);
+        Self::receive_balance(&env, to.clone(), amount);
+        
+        env.events().publish(
+            (symbol_short!("transfer"), from, to),
+            amount,
+        );
+    }
+    
+    /// Mint new shares (admin/pool only)
+    pub fn mint(env: Env, to: Address, amount: i128) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        admin.require_auth();
+        
+        if amount < 0 {
+            panic!("amount cannot be negative");
+        }
+        
+        let total: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalSupply, &(total + amount));
+        
+        Self::receive_balance(&env, to.clone(), amount);
+        
+        env.events().publish(
+            (symbol_short!("mint"), admin, to),
+            amount,
+        );
+    }
+    
+    /// Burn tokens
+    pub fn burn(env: Env, from: Address, amount: i128) {
+        from.require_auth();
+        
+        Self::burn_balance(&env, from, amount);
+    }
+    
+    /// Burn tokens using allowance
+    pub fn burn_from(env: Env, spender: Address, from: Address, amount: i128) {
+        spender.require_auth();
+        
+        Self::spend_allowance(&env, from.clone(), spender, amount);
+        Self::burn_balance(&env, from, amount);
+    }
+    
+    /// Get admin address
+    pub fn admin(env: Env) -> Address {
+        env.storage().instance().get(&DataKey::Admin).unwrap()
+    }
+    
+    // Internal helper: burn from balance and supply
+    fn burn_balance(env: &Env, from: Address, amount: i128) {
+        Self::spend_balance(env, from.clone(), amount);
+        
+        let total: i128 = env.storage().instance().get(&DataKey::TotalSupply).unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalSupply, &(total - amount));
+        
+        env.events().publish(
+            (symbol_short!("burn"), from),
+            amount,
+        );
+    }
+    
+    // Internal helper: read unexpired allowance
+    fn read_allowance(env: &Env, from: Address, spender: Address) -> AllowanceValue {
+        let allowance: Option<AllowanceValue> = env.storage().temporary()
+            .get(&DataKey::Allowance(from, spender));
+        match allowance {
+            Some(a) if a.expiration_ledger >= env.ledger().sequence() => a,
+            _ => AllowanceValue { amount: 0, expiration_ledger: 0 },
+        }
+    }
+    
+    // Internal helper: consume allowance
+    fn spend_allowance(env: &Env, from: Address, spender: Address, amount: i128) {
+        let allowance = Self::read_allowance(env, from.clone(), spender.clone());
+        if allowance.amount < amount {
+            panic!("insufficient allowance");
+        }
+        env.storage().temporary().set(
+            &DataKey::Allowance(from, spender),
+            &AllowanceValue { amount: allowance.amount - amount, expiration_ledger: allowance.expiration_ledger },
+        );
+    }
+    
+    // Internal helper: spend balance
+    fn spend_balance(env: &Env, from: Address, amount: i128) {
+        if amount < 0 {
+            panic!("amount cannot be negative");
+        }
+        
+        let balance = env.storage().persistent().get(&DataKey::Balance(from.clone())).unwrap_or(0i128);
+        if balance < amount {
+            panic!("insufficient balance");
+        }
+        
+        env.storage().persistent().set(&DataKey::Balance(from), &(balance - amount));
+    }
+    
+    // Internal helper: receive balance
+    fn receive_balance(env: &Env, to: Address, amount: i128) {
+        let balance = env.storage().persistent().get(&DataKey::Balance(to.clone())).unwrap_or(0i128);
+        env.storage().persistent().set(&DataKey::Balance(to), &(balance + amount));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::testutils::Address as _;
+
+    #[test]
+    fn test_mint_transfer_burn() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, LPShareToken);
+        let client = LPShareTokenClient::new(&env, &contract_id);
+        
+        let pool = Address::generate(&env);
+        let user = Address::generate(&env);
+        let other = Address::generate(&env);
+        
+        env.mock_all_auths();
+        client.initialize(&pool, &7, &String::from_str(&env, "CCA LP Share"), &String::from_str(&env, "CCA-LP"));
+        
+        client.mint(&user, &1_000);
+        client.transfer(&user, &other, &400);
+        client.burn(&other, &100);
+        
+        assert_eq!(client.balance(&user), 600);
+        assert_eq!(client.balance(&other), 300);
+        assert_eq!(client.total_supply(), 900);
+    }
+}